@@ -10,12 +10,22 @@ pub struct Model {
     #[sea_orm(unique)]
     pub spotify: String,
     pub created_at: TimeDateTimeWithTimeZone,
+    pub webhook_secret: Option<String>,
+    #[sea_orm(unique)]
+    pub feed_token: Option<Uuid>,
+    pub deletion_scheduled_at: Option<TimeDateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+    #[sea_orm(has_many = "super::backup_run::Entity")]
+    BackupRun,
     #[sea_orm(has_one = "super::github_auth::Entity")]
     GithubAuth,
+    #[sea_orm(has_one = "super::notification_settings::Entity")]
+    NotificationSettings,
     #[sea_orm(
         belongs_to = "super::spotify_auth::Entity",
         from = "Column::Spotify",
@@ -26,6 +36,20 @@ pub enum Relation {
     SpotifyAuth,
     #[sea_orm(has_many = "super::user_session::Entity")]
     UserSession,
+    #[sea_orm(has_many = "super::webhook::Entity")]
+    Webhook,
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl Related<super::backup_run::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BackupRun.def()
+    }
 }
 
 impl Related<super::github_auth::Entity> for Entity {
@@ -34,6 +58,12 @@ impl Related<super::github_auth::Entity> for Entity {
     }
 }
 
+impl Related<super::notification_settings::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::NotificationSettings.def()
+    }
+}
+
 impl Related<super::spotify_auth::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SpotifyAuth.def()
@@ -46,4 +76,10 @@ impl Related<super::user_session::Entity> for Entity {
     }
 }
 
+impl Related<super::webhook::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Webhook.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
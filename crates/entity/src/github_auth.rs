@@ -11,6 +11,13 @@ pub struct Model {
     pub user_id: String,
     pub access_token: String,
     pub created_at: TimeDateTimeWithTimeZone,
+    pub installation_id: Option<i64>,
+    pub repository_id: Option<i64>,
+    pub expires_at: Option<TimeDateTimeWithTimeZone>,
+    pub refresh_token: Option<String>,
+    pub refresh_token_expires_at: Option<TimeDateTimeWithTimeZone>,
+    pub token_key_id: String,
+    pub token_data_key: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
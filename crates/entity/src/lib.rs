@@ -3,6 +3,11 @@
 pub mod prelude;
 
 pub mod account;
+pub mod api_token;
+pub mod backup_run;
 pub mod github_auth;
+pub mod notification_settings;
 pub mod spotify_auth;
 pub mod user_session;
+pub mod webhook;
+pub mod webhook_delivery;
@@ -1,6 +1,11 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.2
 
 pub use super::account::Entity as Account;
+pub use super::api_token::Entity as ApiToken;
+pub use super::backup_run::Entity as BackupRun;
 pub use super::github_auth::Entity as GithubAuth;
+pub use super::notification_settings::Entity as NotificationSettings;
 pub use super::spotify_auth::Entity as SpotifyAuth;
 pub use super::user_session::Entity as UserSession;
+pub use super::webhook::Entity as Webhook;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
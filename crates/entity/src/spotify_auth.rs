@@ -11,6 +11,8 @@ pub struct Model {
     pub expires_at: TimeDateTimeWithTimeZone,
     pub refresh_token: String,
     pub created_at: TimeDateTimeWithTimeZone,
+    pub token_key_id: String,
+    pub token_data_key: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
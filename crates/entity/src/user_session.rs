@@ -10,6 +10,11 @@ pub struct Model {
     pub created_at: TimeDateTimeWithTimeZone,
     pub last_seen: TimeDateTimeWithTimeZone,
     pub account: Uuid,
+    pub oauth_state: Option<String>,
+    pub oauth_state_created_at: Option<TimeDateTimeWithTimeZone>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,12 +1,38 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_tables;
+mod m20231201_000002_create_backup_run;
+mod m20231205_000003_create_notification_settings;
+mod m20231208_000004_create_webhooks;
+mod m20231212_000005_add_account_feed_token;
+mod m20231215_000006_add_user_session_oauth_state;
+mod m20231218_000007_add_github_installation;
+mod m20231220_000008_add_github_token_expiry;
+mod m20231222_000009_encrypt_oauth_tokens;
+mod m20231227_000010_add_user_session_client;
+mod m20231229_000011_add_account_deletion;
+mod m20231231_000012_create_api_tokens;
+mod m20240103_000013_add_notification_email_confirmation;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_tables::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_tables::Migration),
+            Box::new(m20231201_000002_create_backup_run::Migration),
+            Box::new(m20231205_000003_create_notification_settings::Migration),
+            Box::new(m20231208_000004_create_webhooks::Migration),
+            Box::new(m20231212_000005_add_account_feed_token::Migration),
+            Box::new(m20231215_000006_add_user_session_oauth_state::Migration),
+            Box::new(m20231218_000007_add_github_installation::Migration),
+            Box::new(m20231220_000008_add_github_token_expiry::Migration),
+            Box::new(m20231222_000009_encrypt_oauth_tokens::Migration),
+            Box::new(m20231227_000010_add_user_session_client::Migration),
+            Box::new(m20231229_000011_add_account_deletion::Migration),
+            Box::new(m20231231_000012_create_api_tokens::Migration),
+            Box::new(m20240103_000013_add_notification_email_confirmation::Migration),
+        ]
     }
 }
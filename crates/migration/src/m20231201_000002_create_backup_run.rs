@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(BackupRun::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BackupRun::Id)
+                            .primary_key()
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BackupRun::Account).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .to(Account::Table, Account::Id)
+                            .from(BackupRun::Table, BackupRun::Account)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(BackupRun::StartedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BackupRun::FinishedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BackupRun::Status).string().not_null())
+                    .col(ColumnDef::new(BackupRun::Commit).string())
+                    .col(ColumnDef::new(BackupRun::Summary).text())
+                    .col(ColumnDef::new(BackupRun::Diff).json_binary())
+                    .col(ColumnDef::new(BackupRun::Error).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-backup_run-account-started_at")
+                    .table(BackupRun::Table)
+                    .col(BackupRun::Account)
+                    .col(BackupRun::StartedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BackupRun::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum BackupRun {
+    Table,
+    Id,
+    Account,
+    StartedAt,
+    FinishedAt,
+    Status,
+    Commit,
+    Summary,
+    Diff,
+    Error,
+}
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Id,
+}
@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(NotificationSettings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NotificationSettings::Account)
+                            .primary_key()
+                            .uuid()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .to(Account::Table, Account::Id)
+                            .from(NotificationSettings::Table, NotificationSettings::Account)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::Email)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::BackupFailures)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::AuthorizationRevoked)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::WeeklySummary)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::UnavailableTracks)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::UnsubscribeToken)
+                            .unique_key()
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::LastWeeklySummary)
+                            .timestamp_with_time_zone(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationSettings::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(NotificationSettings::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum NotificationSettings {
+    Table,
+    Account,
+    Email,
+    BackupFailures,
+    AuthorizationRevoked,
+    WeeklySummary,
+    UnavailableTracks,
+    UnsubscribeToken,
+    LastWeeklySummary,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Id,
+}
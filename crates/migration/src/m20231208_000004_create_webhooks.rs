@@ -0,0 +1,149 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Account::Table)
+                    .add_column(ColumnDef::new(Account::WebhookSecret).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Webhook::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Webhook::Id).primary_key().uuid().not_null())
+                    .col(ColumnDef::new(Webhook::Account).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .to(Account::Table, Account::Id)
+                            .from(Webhook::Table, Webhook::Account)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(Webhook::Url).text().not_null())
+                    .col(
+                        ColumnDef::new(Webhook::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Id)
+                            .primary_key()
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::Webhook).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .to(Webhook::Table, Webhook::Id)
+                            .from(WebhookDelivery::Table, WebhookDelivery::Webhook)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::BackupRun).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .to(BackupRun::Table, BackupRun::Id)
+                            .from(WebhookDelivery::Table, WebhookDelivery::BackupRun)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Attempt)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::AttemptedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::StatusCode).small_integer())
+                    .col(ColumnDef::new(WebhookDelivery::Error).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-webhook_delivery-webhook-attempted_at")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::Webhook)
+                    .col(WebhookDelivery::AttemptedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Webhook::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Account::Table)
+                    .drop_column(Account::WebhookSecret)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Webhook {
+    Table,
+    Id,
+    Account,
+    Url,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    Webhook,
+    BackupRun,
+    Attempt,
+    AttemptedAt,
+    StatusCode,
+    Error,
+}
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Id,
+    WebhookSecret,
+}
+
+#[derive(Iden)]
+enum BackupRun {
+    Table,
+    Id,
+}
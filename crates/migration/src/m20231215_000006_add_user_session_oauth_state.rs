@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserSession::Table)
+                    .add_column(ColumnDef::new(UserSession::OauthState).string())
+                    .add_column(
+                        ColumnDef::new(UserSession::OauthStateCreatedAt).timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserSession::Table)
+                    .drop_column(UserSession::OauthState)
+                    .drop_column(UserSession::OauthStateCreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserSession {
+    Table,
+    OauthState,
+    OauthStateCreatedAt,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GithubAuth::Table)
+                    .add_column(ColumnDef::new(GithubAuth::InstallationId).big_integer())
+                    .add_column(ColumnDef::new(GithubAuth::RepositoryId).big_integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GithubAuth::Table)
+                    .drop_column(GithubAuth::InstallationId)
+                    .drop_column(GithubAuth::RepositoryId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum GithubAuth {
+    Table,
+    InstallationId,
+    RepositoryId,
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GithubAuth::Table)
+                    .add_column(ColumnDef::new(GithubAuth::ExpiresAt).timestamp_with_time_zone())
+                    .add_column(ColumnDef::new(GithubAuth::RefreshToken).string())
+                    .add_column(
+                        ColumnDef::new(GithubAuth::RefreshTokenExpiresAt)
+                            .timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(GithubAuth::Table)
+                    .drop_column(GithubAuth::ExpiresAt)
+                    .drop_column(GithubAuth::RefreshToken)
+                    .drop_column(GithubAuth::RefreshTokenExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum GithubAuth {
+    Table,
+    ExpiresAt,
+    RefreshToken,
+    RefreshTokenExpiresAt,
+}
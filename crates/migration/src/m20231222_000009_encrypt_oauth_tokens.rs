@@ -0,0 +1,228 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+use token_encryption::{Keyring, KEYS_VARIABLE};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let keyring = keyring()?;
+
+        for table in TokenTable::ALL {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.table())
+                        .add_column(ColumnDef::new(Auth::KeyId).string())
+                        .add_column(ColumnDef::new(Auth::DataKey).string())
+                        .to_owned(),
+                )
+                .await?;
+
+            table.encrypt_rows(manager, &keyring).await?;
+
+            // Every row has a data key from now on
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.table())
+                        .modify_column(ColumnDef::new(Auth::KeyId).string().not_null())
+                        .modify_column(ColumnDef::new(Auth::DataKey).string().not_null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let keyring = keyring()?;
+
+        for table in TokenTable::ALL {
+            table.decrypt_rows(manager, &keyring).await?;
+
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.table())
+                        .drop_column(Auth::KeyId)
+                        .drop_column(Auth::DataKey)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn keyring() -> Result<Keyring, DbErr> {
+    let spec = std::env::var(KEYS_VARIABLE)
+        .map_err(|_| DbErr::Custom(format!("${KEYS_VARIABLE} should be set")))?;
+
+    Keyring::parse(&spec).map_err(|error| DbErr::Custom(error.to_string()))
+}
+
+#[derive(Clone, Copy)]
+enum TokenTable {
+    Spotify,
+    Github,
+}
+
+impl TokenTable {
+    const ALL: [TokenTable; 2] = [TokenTable::Spotify, TokenTable::Github];
+
+    fn table(self) -> DynIden {
+        match self {
+            TokenTable::Spotify => SpotifyAuth::Table.into_iden(),
+            TokenTable::Github => GithubAuth::Table.into_iden(),
+        }
+    }
+
+    async fn rows(self, manager: &SchemaManager<'_>) -> Result<Vec<TokenRow>, DbErr> {
+        let connection = manager.get_connection();
+
+        let select = Query::select()
+            .columns([
+                Auth::UserId,
+                Auth::AccessToken,
+                Auth::RefreshToken,
+                Auth::KeyId,
+                Auth::DataKey,
+            ])
+            .from(self.table())
+            .to_owned();
+
+        connection
+            .query_all(connection.get_database_backend().build(&select))
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(TokenRow {
+                    user_id: row.try_get("", &Auth::UserId.to_string())?,
+                    access_token: row.try_get("", &Auth::AccessToken.to_string())?,
+                    refresh_token: row.try_get("", &Auth::RefreshToken.to_string())?,
+                    key_id: row.try_get("", &Auth::KeyId.to_string())?,
+                    data_key: row.try_get("", &Auth::DataKey.to_string())?,
+                })
+            })
+            .collect()
+    }
+
+    async fn update(
+        self,
+        manager: &SchemaManager<'_>,
+        user_id: String,
+        values: Vec<(Auth, SimpleExpr)>,
+    ) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(self.table())
+                    .values(values)
+                    .and_where(Expr::col(Auth::UserId).eq(user_id))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn encrypt_rows(
+        self,
+        manager: &SchemaManager<'_>,
+        keyring: &Keyring,
+    ) -> Result<(), DbErr> {
+        for row in self.rows(manager).await? {
+            let envelope = keyring.new_envelope();
+            let encrypt = |field: Auth, value: Option<String>| {
+                value.map(|value| envelope.encrypt(&field.to_string(), &value))
+            };
+
+            let values = vec![
+                (
+                    Auth::AccessToken,
+                    encrypt(Auth::AccessToken, row.access_token).into(),
+                ),
+                (
+                    Auth::RefreshToken,
+                    encrypt(Auth::RefreshToken, row.refresh_token).into(),
+                ),
+                (Auth::KeyId, envelope.key_id.clone().into()),
+                (Auth::DataKey, envelope.wrapped_key.clone().into()),
+            ];
+
+            self.update(manager, row.user_id, values).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn decrypt_rows(
+        self,
+        manager: &SchemaManager<'_>,
+        keyring: &Keyring,
+    ) -> Result<(), DbErr> {
+        for row in self.rows(manager).await? {
+            let (Some(key_id), Some(data_key)) = (row.key_id, row.data_key) else {
+                continue;
+            };
+            let envelope = keyring
+                .open_envelope(&key_id, &data_key)
+                .map_err(|error| DbErr::Custom(error.to_string()))?;
+
+            let decrypt = |field: Auth, value: Option<String>| {
+                value
+                    .map(|value| envelope.decrypt(&field.to_string(), &value))
+                    .transpose()
+                    .map_err(|error| DbErr::Custom(error.to_string()))
+            };
+
+            let values = vec![
+                (
+                    Auth::AccessToken,
+                    decrypt(Auth::AccessToken, row.access_token)?.into(),
+                ),
+                (
+                    Auth::RefreshToken,
+                    decrypt(Auth::RefreshToken, row.refresh_token)?.into(),
+                ),
+            ];
+
+            self.update(manager, row.user_id, values).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The access token is never null, but the github refresh token can be
+struct TokenRow {
+    user_id: String,
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    key_id: Option<String>,
+    data_key: Option<String>,
+}
+
+#[derive(Iden)]
+enum SpotifyAuth {
+    Table,
+}
+
+#[derive(Iden)]
+enum GithubAuth {
+    Table,
+}
+
+/// Columns shared by both authentication tables
+#[derive(Iden, Clone, Copy)]
+enum Auth {
+    UserId,
+    AccessToken,
+    RefreshToken,
+    #[iden = "token_key_id"]
+    KeyId,
+    #[iden = "token_data_key"]
+    DataKey,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserSession::Table)
+                    .add_column(ColumnDef::new(UserSession::UserAgent).text())
+                    .add_column(ColumnDef::new(UserSession::IpAddress).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserSession::Table)
+                    .drop_column(UserSession::UserAgent)
+                    .drop_column(UserSession::IpAddress)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserSession {
+    Table,
+    UserAgent,
+    IpAddress,
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Existing addresses were never confirmed, so they stop receiving emails
+/// until the settings are saved again and the address is confirmed
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NotificationSettings::Table)
+                    .add_column(
+                        ColumnDef::new(NotificationSettings::EmailConfirmedAt)
+                            .timestamp_with_time_zone(),
+                    )
+                    .add_column(
+                        ColumnDef::new(NotificationSettings::ConfirmationToken)
+                            .uuid()
+                            .unique_key(),
+                    )
+                    .add_column(
+                        ColumnDef::new(NotificationSettings::ConfirmationSentAt)
+                            .timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NotificationSettings::Table)
+                    .drop_column(NotificationSettings::EmailConfirmedAt)
+                    .drop_column(NotificationSettings::ConfirmationToken)
+                    .drop_column(NotificationSettings::ConfirmationSentAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum NotificationSettings {
+    Table,
+    EmailConfirmedAt,
+    ConfirmationToken,
+    ConfirmationSentAt,
+}
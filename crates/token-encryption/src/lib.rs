@@ -0,0 +1,344 @@
+//! Envelope encryption of OAuth tokens stored in the database.
+//!
+//! Every row gets its own random data key, which encrypts the tokens of that
+//! row. The data key is stored next to them, wrapped by one of the master keys
+//! from the environment, along with the id of that master key. Rotating the
+//! master key only requires wrapping the data keys again.
+
+use std::{collections::HashMap, fmt};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// Environment variable with the master keys, see [`Keyring::parse`]
+pub const KEYS_VARIABLE: &str = "TOKEN_ENCRYPTION_KEYS";
+
+const KEY_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The keyring specification is malformed
+    InvalidKeyring(&'static str),
+    /// The row was encrypted with a master key that is not in the keyring
+    UnknownKey(String),
+    /// The ciphertext is malformed, or was not encrypted with this key
+    Decryption,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidKeyring(reason) => write!(f, "invalid keyring: {reason}"),
+            Error::UnknownKey(key_id) => write!(f, "master key {key_id:?} is not in the keyring"),
+            Error::Decryption => write!(f, "ciphertext could not be decrypted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The master keys, the first one is used to wrap new data keys and the rest
+/// are only kept around to unwrap data keys until they are re-encrypted
+pub struct Keyring {
+    current: String,
+    keys: HashMap<String, LessSafeKey>,
+}
+
+impl Keyring {
+    /// Parse a comma separated list of `id:key` pairs, where every key is 32
+    /// hex encoded bytes, for example `2023-12:<hex>,2023-06:<hex>`
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut current = None;
+        let mut keys = HashMap::new();
+
+        for entry in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (id, key) = entry.split_once(':').ok_or(Error::InvalidKeyring(
+                "every key must be prefixed with its id",
+            ))?;
+            let key = hex::decode(key).map_err(|_| Error::InvalidKeyring("keys must be hex"))?;
+            if key.len() != KEY_LEN {
+                return Err(Error::InvalidKeyring("keys must be 32 bytes long"));
+            }
+
+            let key = UnboundKey::new(&AES_256_GCM, &key)
+                .map_err(|_| Error::InvalidKeyring("keys must be 32 bytes long"))?;
+            if keys.insert(id.to_string(), LessSafeKey::new(key)).is_some() {
+                return Err(Error::InvalidKeyring("key ids must be unique"));
+            }
+
+            current.get_or_insert_with(|| id.to_string());
+        }
+
+        Ok(Self {
+            current: current.ok_or(Error::InvalidKeyring("at least one key is required"))?,
+            keys,
+        })
+    }
+
+    pub fn current_key_id(&self) -> &str {
+        &self.current
+    }
+
+    /// A new data key for a row, wrapped by the current master key
+    pub fn new_envelope(&self) -> Envelope {
+        let mut data_key = [0; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut data_key)
+            .expect("system random should be available");
+
+        let master_key = &self.keys[&self.current];
+
+        Envelope {
+            key_id: self.current.clone(),
+            wrapped_key: seal(master_key, &self.current, &data_key),
+            data_key: data_key_from_bytes(&data_key),
+        }
+    }
+
+    /// Unwrap the data key of a row
+    pub fn open_envelope(&self, key_id: &str, wrapped_key: &str) -> Result<Envelope, Error> {
+        let data_key = self.unwrap_key(key_id, wrapped_key)?;
+
+        Ok(Envelope {
+            key_id: key_id.to_string(),
+            wrapped_key: wrapped_key.to_string(),
+            data_key: data_key_from_bytes(&data_key),
+        })
+    }
+
+    /// Wrap the data key of a row with the current master key, returns `None`
+    /// if it already is
+    pub fn rewrap(&self, key_id: &str, wrapped_key: &str) -> Result<Option<String>, Error> {
+        if key_id == self.current {
+            return Ok(None);
+        }
+
+        let data_key = self.unwrap_key(key_id, wrapped_key)?;
+
+        Ok(Some(seal(
+            &self.keys[&self.current],
+            &self.current,
+            &data_key,
+        )))
+    }
+
+    fn unwrap_key(&self, key_id: &str, wrapped_key: &str) -> Result<Vec<u8>, Error> {
+        let master_key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| Error::UnknownKey(key_id.to_string()))?;
+
+        let data_key = open(master_key, key_id, wrapped_key)?;
+        if data_key.len() != KEY_LEN {
+            return Err(Error::Decryption);
+        }
+
+        Ok(data_key)
+    }
+}
+
+/// The data key of a single row
+pub struct Envelope {
+    /// Id of the master key that wrapped the data key
+    pub key_id: String,
+    /// The data key, encrypted by the master key
+    pub wrapped_key: String,
+    data_key: LessSafeKey,
+}
+
+impl Envelope {
+    /// Encrypt a value, `field` must be the same when decrypting so values
+    /// can not be swapped between columns
+    pub fn encrypt(&self, field: &str, plaintext: &str) -> String {
+        seal(&self.data_key, field, plaintext.as_bytes())
+    }
+
+    pub fn decrypt(&self, field: &str, ciphertext: &str) -> Result<String, Error> {
+        String::from_utf8(open(&self.data_key, field, ciphertext)?).map_err(|_| Error::Decryption)
+    }
+}
+
+fn data_key_from_bytes(data_key: &[u8]) -> LessSafeKey {
+    LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, data_key).expect("data keys should be 32 bytes long"),
+    )
+}
+
+/// Base64 of the random nonce followed by the ciphertext and tag
+fn seal(key: &LessSafeKey, aad: &str, plaintext: &[u8]) -> String {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .expect("system random should be available");
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad.as_bytes()),
+        &mut in_out,
+    )
+    .expect("plaintext should not be too long to encrypt");
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(in_out);
+
+    STANDARD.encode(sealed)
+}
+
+fn open(key: &LessSafeKey, aad: &str, sealed: &str) -> Result<Vec<u8>, Error> {
+    let sealed = STANDARD.decode(sealed).map_err(|_| Error::Decryption)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Decryption);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Error::Decryption)?;
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad.as_bytes()), &mut in_out)
+        .map_err(|_| Error::Decryption)?;
+
+    Ok(plaintext.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: &str) -> String {
+        byte.repeat(KEY_LEN)
+    }
+
+    fn keyring(spec: &[(&str, &str)]) -> Keyring {
+        let spec = spec
+            .iter()
+            .map(|(id, byte)| format!("{id}:{}", key(byte)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Keyring::parse(&spec).expect("keyring should be valid")
+    }
+
+    fn invalid_keyring(spec: &str) -> &'static str {
+        match Keyring::parse(spec) {
+            Err(Error::InvalidKeyring(reason)) => reason,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("keyring should be invalid"),
+        }
+    }
+
+    #[test]
+    fn seal_and_open_round_trip() {
+        let keyring = keyring(&[("new", "11")]);
+
+        let envelope = keyring.new_envelope();
+        assert_eq!(envelope.key_id, "new");
+        let ciphertext = envelope.encrypt("access_token", "secret");
+        assert_ne!(ciphertext, "secret");
+
+        let opened = keyring
+            .open_envelope(&envelope.key_id, &envelope.wrapped_key)
+            .expect("envelope should open");
+        assert_eq!(
+            opened
+                .decrypt("access_token", &ciphertext)
+                .expect("ciphertext should decrypt"),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn open_with_wrong_field_fails() {
+        let keyring = keyring(&[("new", "11")]);
+
+        let envelope = keyring.new_envelope();
+        let ciphertext = envelope.encrypt("access_token", "secret");
+
+        assert!(matches!(
+            envelope.decrypt("refresh_token", &ciphertext),
+            Err(Error::Decryption)
+        ));
+    }
+
+    #[test]
+    fn rewrap_to_new_key() {
+        let old = keyring(&[("old", "11")]);
+        let envelope = old.new_envelope();
+        let ciphertext = envelope.encrypt("access_token", "secret");
+
+        let rotated = keyring(&[("new", "22"), ("old", "11")]);
+        let wrapped_key = rotated
+            .rewrap(&envelope.key_id, &envelope.wrapped_key)
+            .expect("data key should rewrap")
+            .expect("data key should not use the current key yet");
+        assert_eq!(
+            rotated
+                .rewrap("new", &wrapped_key)
+                .expect("rewrap should succeed"),
+            None
+        );
+
+        // The old master key is no longer needed
+        let new = keyring(&[("new", "22")]);
+        let opened = new
+            .open_envelope("new", &wrapped_key)
+            .expect("rewrapped envelope should open");
+        assert_eq!(
+            opened
+                .decrypt("access_token", &ciphertext)
+                .expect("ciphertext should decrypt"),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn open_with_unknown_key_fails() {
+        let envelope = keyring(&[("old", "11")]).new_envelope();
+
+        assert!(matches!(
+            keyring(&[("new", "22")]).open_envelope(&envelope.key_id, &envelope.wrapped_key),
+            Err(Error::UnknownKey(key_id)) if key_id == "old"
+        ));
+    }
+
+    #[test]
+    fn open_with_wrong_master_key_fails() {
+        let envelope = keyring(&[("same", "11")]).new_envelope();
+
+        assert!(matches!(
+            keyring(&[("same", "22")]).open_envelope(&envelope.key_id, &envelope.wrapped_key),
+            Err(Error::Decryption)
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            invalid_keyring(&key("11")),
+            "every key must be prefixed with its id"
+        );
+        assert_eq!(invalid_keyring("id:not hex"), "keys must be hex");
+        assert_eq!(invalid_keyring("id:1111"), "keys must be 32 bytes long");
+        assert_eq!(
+            invalid_keyring(&format!("id:{},id:{}", key("11"), key("22"))),
+            "key ids must be unique"
+        );
+        assert_eq!(invalid_keyring(" , "), "at least one key is required");
+    }
+
+    #[test]
+    fn first_key_is_current() {
+        let keyring = keyring(&[("new", "22"), ("old", "11")]);
+
+        assert_eq!(keyring.current_key_id(), "new");
+        assert_eq!(keyring.new_envelope().key_id, "new");
+    }
+}
@@ -0,0 +1,122 @@
+use rand::RngCore;
+use secrecy::SecretString;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use crate::database::id::{AccountId, ApiTokenId};
+
+/// Every token starts with this, so that leaked tokens are easy to recognise
+pub const TOKEN_PREFIX: &str = "sbk_";
+
+/// Maximum number of tokens a single account can have
+pub const MAX_API_TOKENS: usize = 10;
+
+/// What a personal api token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    BackupsRead,
+    BackupsWrite,
+    SnapshotsRead,
+    SettingsRead,
+    SettingsWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 5] = [
+        ApiScope::BackupsRead,
+        ApiScope::BackupsWrite,
+        ApiScope::SnapshotsRead,
+        ApiScope::SettingsRead,
+        ApiScope::SettingsWrite,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::BackupsRead => "backups:read",
+            ApiScope::BackupsWrite => "backups:write",
+            ApiScope::SnapshotsRead => "snapshots:read",
+            ApiScope::SettingsRead => "settings:read",
+            ApiScope::SettingsWrite => "settings:write",
+        }
+    }
+
+    pub const fn description(&self) -> &'static str {
+        match self {
+            ApiScope::BackupsRead => "list backup runs",
+            ApiScope::BackupsWrite => "start a backup",
+            ApiScope::SnapshotsRead => "read snapshots and compare them",
+            ApiScope::SettingsRead => "read notification settings",
+            ApiScope::SettingsWrite => "change notification settings",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == scope)
+    }
+}
+
+/// A token that authenticates requests to the json api on behalf of an
+/// account, only a hash of it is stored
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: ApiTokenId,
+    pub account: AccountId,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+impl ApiToken {
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn from_model(model: entity::api_token::Model) -> Self {
+        Self {
+            id: ApiTokenId::from_model(&model),
+            account: AccountId::from_api_token(&model),
+            scopes: model
+                .scopes
+                .split(' ')
+                .filter_map(ApiScope::parse)
+                .collect(),
+            name: model.name,
+            created_at: model.created_at,
+            last_used_at: model.last_used_at,
+        }
+    }
+
+    pub fn into_model(self, token_hash: String) -> entity::api_token::Model {
+        entity::api_token::Model {
+            id: self.id.into_uuid(),
+            account: self.account.into_uuid(),
+            name: self.name,
+            token_hash,
+            scopes: self
+                .scopes
+                .iter()
+                .map(ApiScope::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+}
+
+/// A new random token, shown to the user once
+pub fn generate_token() -> SecretString {
+    let mut token = [0; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+
+    SecretString::new(format!("{TOKEN_PREFIX}{}", hex::encode(token)))
+}
+
+/// What is stored in place of the token, the token is random enough that a
+/// plain hash can not be reversed
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -1,18 +1,152 @@
-use std::{convert::Infallible, time::Duration};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use futures::{StreamExt, TryStreamExt};
-use rspotify::clients::OAuthClient;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use sentry::SentryFutureExt;
+use time::OffsetDateTime;
 use tokio::time::Instant;
-use tracing::{error_span, Level};
+use tracing::Level;
 
 use crate::{
-    database::Database,
+    database::{
+        id::{AccountId, BackupRunId},
+        Database,
+    },
+    metrics,
+    notifications::{self, Mailer},
     pages::InternalServerError,
-    router::authentication::{github::GithubAuthentication, Account},
+    router::{
+        self,
+        authentication::{github::GithubInstallation, Account},
+    },
+    webhooks::{WebhookPayload, WebhookSender},
 };
 
+use self::{diff::SnapshotDiff, github::BackupRepository, snapshot::Snapshot};
+
+pub mod diff;
+pub mod github;
+pub mod snapshot;
+
+/// Whether the hourly backup loop is running, it only stops when it panics
+static WORKER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Unix timestamp of when the backup loop started or last started a pass
+static WORKER_LAST_TICK: AtomicI64 = AtomicI64::new(0);
+
+/// A pass that has not finished by the time the next one is due is stuck,
+/// with some margin for passes that take a while
+const WORKER_STUCK_AFTER: time::Duration = time::Duration::hours(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerStatus {
+    Running,
+    /// Still running, but has not started a pass for too long
+    Stuck {
+        last_tick: OffsetDateTime,
+    },
+    /// The backup loop panicked
+    Stopped,
+}
+
+pub fn worker_status() -> WorkerStatus {
+    if !WORKER_RUNNING.load(Ordering::Relaxed) {
+        return WorkerStatus::Stopped;
+    }
+
+    let last_tick = OffsetDateTime::from_unix_timestamp(WORKER_LAST_TICK.load(Ordering::Relaxed))
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+
+    if OffsetDateTime::now_utc() - last_tick > WORKER_STUCK_AFTER {
+        WorkerStatus::Stuck { last_tick }
+    } else {
+        WorkerStatus::Running
+    }
+}
+
+fn record_worker_tick() {
+    WORKER_LAST_TICK.store(
+        OffsetDateTime::now_utc().unix_timestamp(),
+        Ordering::Relaxed,
+    );
+}
+
+/// Marks the backup loop as running until it is dropped
+struct WorkerRunning;
+
+impl WorkerRunning {
+    fn start() -> Self {
+        record_worker_tick();
+        WORKER_RUNNING.store(true, Ordering::Relaxed);
+
+        WorkerRunning
+    }
+}
+
+impl Drop for WorkerRunning {
+    fn drop(&mut self) {
+        WORKER_RUNNING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Accounts with a backup that has not finished yet, either from the hourly
+/// schedule or requested outside of it
+static BACKUPS_RUNNING: Lazy<Mutex<HashSet<AccountId>>> = Lazy::new(Default::default);
+
+/// Marks a backup of the account as running until it is dropped, so that the
+/// account is released even if the backup panics
+struct BackupRunning {
+    account: AccountId,
+    requested: bool,
+}
+
+impl BackupRunning {
+    /// None if a backup of the account is already running
+    fn start(account: AccountId, requested: bool) -> Option<Self> {
+        let inserted = BACKUPS_RUNNING
+            .lock()
+            .expect("running backups lock should not be poisoned")
+            .insert(account);
+        if !inserted {
+            return None;
+        }
+
+        if requested {
+            metrics::BACKUP_REQUESTS_PENDING.inc();
+        }
+
+        Some(Self { account, requested })
+    }
+}
+
+impl Drop for BackupRunning {
+    fn drop(&mut self) {
+        // Do not panic again while unwinding, the set is still consistent as
+        // it is only modified by single insert and remove calls
+        BACKUPS_RUNNING
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&self.account);
+
+        if self.requested {
+            metrics::BACKUP_REQUESTS_PENDING.dec();
+        }
+    }
+}
+
 #[tracing::instrument(skip_all)]
-pub async fn backup(database: Database) -> Infallible {
+pub async fn backup(database: Database, mailer: Mailer, webhooks: WebhookSender) -> Infallible {
+    let _running = WorkerRunning::start();
+    let reqwest = router::http_client();
+
     let now = time::OffsetDateTime::now_utc();
     let instant_now = Instant::now();
 
@@ -25,9 +159,11 @@ pub async fn backup(database: Database) -> Infallible {
     let hour = Duration::from_secs(60 * 60);
     let mut interval = tokio::time::interval_at(instant_now + time_till_next_hour, hour);
 
-    #[allow(clippy::never_loop)]
     loop {
-        // interval.tick().await;
+        interval.tick().await;
+        record_worker_tick();
+
+        delete_scheduled_accounts(&database, &reqwest).await;
 
         tracing::info!("processing backups");
 
@@ -40,7 +176,13 @@ pub async fn backup(database: Database) -> Infallible {
             .for_each_concurrent(Some(concurrency as usize), |account| async {
                 match account {
                     Ok(account) => {
-                        let _ = backup_user(account).await;
+                        let Some(_running) = BackupRunning::start(account.id, false) else {
+                            tracing::debug!(account = %account.id, "backup already running... skipping");
+
+                            return;
+                        };
+
+                        backup_user(&database, &mailer, &webhooks, account).await
                     }
                     Err(error) => {
                         tracing::error!(?error, "unable to acquire next user");
@@ -49,68 +191,251 @@ pub async fn backup(database: Database) -> Infallible {
             })
             .await;
 
-        panic!();
+        // The error has already been reported, summaries are retried next hour
+        let _ = notifications::send_weekly_summaries(&database, &mailer).await;
     }
 }
 
-#[tracing::instrument(skip_all, fields(account = %account.id), err(level = Level::WARN))]
-async fn backup_user(account: Account) -> Result<(), InternalServerError> {
-    let github = match account.github.as_ref().map(GithubAuthentication::as_client) {
-        Some(Ok(github)) => github,
-        Some(Err(error)) => {
-            // tracing::warn!(%error, "failed to create github client from authentication details");
+/// Delete the accounts whose grace period has passed, and revoke what they
+/// authorized upstream
+#[tracing::instrument(skip_all)]
+async fn delete_scheduled_accounts(database: &Database, reqwest: &reqwest::Client) {
+    let now = OffsetDateTime::now_utc();
 
-            return Err(error);
-        }
-        None => {
-            tracing::trace!("incomplete user, missing github account... skipping");
+    // The errors have already been reported, deletion is retried next hour
+    let Ok(accounts) = database.accounts_due_for_deletion(now).await else {
+        return;
+    };
+
+    for account in accounts {
+        if let Ok(true) = database.delete_scheduled_account(&account, now).await {
+            tracing::info!(account = %account.id, "deleted account scheduled for deletion");
 
-            return Ok(());
+            account.revoke_upstream_grants(reqwest).await;
         }
+    }
+}
+
+/// Back up the account right away instead of waiting for the next hour,
+/// returns false if a backup of the account is still running
+pub fn request_backup(
+    database: Database,
+    mailer: Mailer,
+    webhooks: WebhookSender,
+    account: Account,
+) -> bool {
+    let Some(running) = BackupRunning::start(account.id, true) else {
+        return false;
     };
-    let spotify_client = account.spotify.as_client();
 
-    // TODO: do this a bit smarter... somehow
-    let mut downloaded_tracks = 0;
-    let mut saved_tracks = Vec::new();
-    loop {
-        let start = Instant::now();
-        let mut page = InternalServerError::wrap(
-            spotify_client.current_user_saved_tracks_manual(
-                None,
-                Some(rspotify::DEFAULT_PAGINATION_CHUNKS),
-                Some(downloaded_tracks),
-            ),
-            error_span!("fetch_page"),
-        )
+    tokio::spawn(async move {
+        let _running = running;
+
+        backup_user(&database, &mailer, &webhooks, account).await;
+    });
+
+    true
+}
+
+/// Back up the account, with the sentry events of the backup tagged with it
+async fn backup_user(
+    database: &Database,
+    mailer: &Mailer,
+    webhooks: &WebhookSender,
+    account: Account,
+) {
+    // Accounts are backed up concurrently on the same task, so each needs its
+    // own scope
+    let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+    hub.configure_scope(|scope| scope.set_tag("account", account.id));
+
+    backup_user_in_hub(database, mailer, webhooks, account)
+        .bind_hub(hub)
+        .await
+}
+
+#[tracing::instrument(skip_all, fields(account = %account.id))]
+async fn backup_user_in_hub(
+    database: &Database,
+    mailer: &Mailer,
+    webhooks: &WebhookSender,
+    account: Account,
+) {
+    if account.deletion_scheduled_at.is_some() {
+        tracing::trace!("account is scheduled for deletion... skipping");
+
+        return;
+    }
+
+    let Some(installation) = account.backup_installation() else {
+        tracing::trace!("incomplete user, missing backup repository... skipping");
+
+        return;
+    };
+
+    metrics::BACKUPS_IN_PROGRESS.inc();
+    let started_at = OffsetDateTime::now_utc();
+    let outcome = match backup_account(&account, installation).await {
+        Ok(outcome) => outcome,
+        Err(error) => BackupOutcome::Failed {
+            error: error.inner_error(),
+        },
+    };
+    let finished_at = OffsetDateTime::now_utc();
+    metrics::BACKUPS_IN_PROGRESS.dec();
+
+    metrics::BACKUP_RUNS
+        .with_label_values(&[outcome.status()])
+        .inc();
+    metrics::BACKUP_RUN_DURATION
+        .with_label_values(&[outcome.status()])
+        .observe((finished_at - started_at).as_seconds_f64());
+
+    let run = BackupRun {
+        id: BackupRunId::generate(),
+        account: account.id,
+        started_at,
+        finished_at,
+        outcome,
+    };
+
+    // The errors have already been reported, there is nowhere else to send them
+    let _ = notifications::notify_backup_run(database, mailer, &account, &run).await;
+
+    let payload = WebhookPayload::from_run(&run);
+    if database.record_backup_run(run).await.is_ok() {
+        let _ = webhooks.dispatch(database, payload).await;
+    }
+}
+
+#[tracing::instrument(skip_all, err(level = Level::WARN))]
+async fn backup_account(
+    account: &Account,
+    installation: &GithubInstallation,
+) -> Result<BackupOutcome, InternalServerError> {
+    let repository = BackupRepository::for_installation(installation).await?;
+
+    let snapshot = Snapshot::fetch(&account.spotify.as_client()).await?;
+
+    let head = repository.head().await?;
+    let previous = repository.snapshot_at(&head.commit).await?;
+
+    let diff = SnapshotDiff::between(&previous, &snapshot);
+    if diff.is_empty() {
+        tracing::debug!("library unchanged since last backup");
+
+        return Ok(BackupOutcome::Unchanged);
+    }
+
+    let commit = repository
+        .commit_snapshot(&head, &snapshot, &diff.commit_message())
         .await?;
 
-        downloaded_tracks += page.items.len() as u32;
-        let total_tracks: u32 = page.total;
+    tracing::info!(commit, summary = diff.summary(), "committed backup");
 
-        let request_nr = downloaded_tracks.div_ceil(rspotify::DEFAULT_PAGINATION_CHUNKS);
-        let total_requests = total_tracks.div_ceil(rspotify::DEFAULT_PAGINATION_CHUNKS);
+    Ok(BackupOutcome::Committed {
+        commit,
+        summary: diff.summary(),
+        diff: Box::new(diff),
+    })
+}
 
-        tracing::trace!(
-            request = format_args!("{request_nr}/{total_requests}"),
-            song = format_args!("{downloaded_tracks}/{total_tracks}"),
-            percent = format_args!(
-                "{:.2}%",
-                100.0 * downloaded_tracks as f32 / total_tracks as f32
-            ),
-            elapsed = ?start.elapsed()
-        );
+#[derive(Debug)]
+pub struct BackupRun {
+    pub id: BackupRunId,
+    pub account: AccountId,
+    pub started_at: OffsetDateTime,
+    pub finished_at: OffsetDateTime,
+    pub outcome: BackupOutcome,
+}
 
-        saved_tracks.append(&mut page.items);
+#[derive(Debug)]
+pub enum BackupOutcome {
+    /// The library changed and a new snapshot was committed
+    Committed {
+        commit: String,
+        summary: String,
+        diff: Box<SnapshotDiff>,
+    },
+    /// The library did not change since the last snapshot
+    Unchanged,
+    Failed {
+        error: String,
+    },
+}
+
+impl BackupOutcome {
+    pub const fn status(&self) -> &'static str {
+        match self {
+            BackupOutcome::Committed { .. } => "committed",
+            BackupOutcome::Unchanged => "unchanged",
+            BackupOutcome::Failed { .. } => "failed",
+        }
+    }
+}
+
+impl BackupRun {
+    pub fn from_model(model: entity::backup_run::Model) -> Self {
+        let outcome = match model.status.as_str() {
+            "committed" => BackupOutcome::Committed {
+                commit: model.commit.clone().unwrap_or_default(),
+                summary: model.summary.clone().unwrap_or_default(),
+                diff: model
+                    .diff
+                    .clone()
+                    .and_then(|diff| match serde_json::from_value(diff) {
+                        Ok(diff) => Some(diff),
+                        Err(error) => {
+                            tracing::warn!(%error, run = %model.id, "stored snapshot diff is invalid");
+                            None
+                        }
+                    })
+                    .map(Box::new)
+                    .unwrap_or_default(),
+            },
+            "unchanged" => BackupOutcome::Unchanged,
+            _ => BackupOutcome::Failed {
+                error: model.error.clone().unwrap_or_default(),
+            },
+        };
 
-        if page.next.is_none() {
-            break;
+        Self {
+            id: BackupRunId::from_model(&model),
+            account: AccountId::from_backup_run(&model),
+            started_at: model.started_at,
+            finished_at: model.finished_at,
+            outcome,
         }
     }
 
-    tokio::fs::write("target/tracks_test.rs", format!("{saved_tracks:#?}")).await;
-    // TODO:
-    // spotify_client.current_user_saved_albums(market)
+    pub fn into_model(self) -> entity::backup_run::Model {
+        let status = self.outcome.status().to_string();
 
-    Ok(())
+        let (commit, summary, diff, error) = match self.outcome {
+            BackupOutcome::Committed {
+                commit,
+                summary,
+                diff,
+            } => (
+                Some(commit),
+                Some(summary),
+                Some(serde_json::to_value(diff).expect("snapshot diff should serialize")),
+                None,
+            ),
+            BackupOutcome::Unchanged => (None, None, None, None),
+            BackupOutcome::Failed { error } => (None, None, None, Some(error)),
+        };
+
+        entity::backup_run::Model {
+            id: self.id.into_uuid(),
+            account: self.account.into_uuid(),
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            status,
+            commit,
+            summary,
+            diff,
+            error,
+        }
+    }
 }
@@ -0,0 +1,645 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use rspotify::{
+    model::{FullTrack, PlayableItem, SavedTrack},
+    prelude::Id,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::snapshot::{
+    is_playable, is_playable_item, playable_key, track_key, PlaylistSnapshot, Snapshot,
+};
+
+/// Number of changes that are listed in the commit subject before they get
+/// collapsed into a count
+const SUBJECT_CHANGES: usize = 5;
+/// Number of tracks listed per section in the commit body
+const BODY_TRACKS: usize = 50;
+
+/// Structured changes between two [`Snapshot`]s
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotDiff {
+    pub liked_songs: TracksDiff,
+    pub playlists_created: Vec<PlaylistSummary>,
+    pub playlists_deleted: Vec<PlaylistSummary>,
+    pub playlists_renamed: Vec<PlaylistRename>,
+    pub playlists_modified: Vec<PlaylistDiff>,
+    /// Tracks that were playable in the previous snapshot but are no longer
+    /// playable in the user's market. Tracks that Spotify stops returning
+    /// altogether are not listed here, the api gives no way to tell them apart
+    /// from tracks the user removed, so they are only listed as removed.
+    #[serde(default)]
+    pub became_unavailable: Vec<TrackSummary>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TracksDiff {
+    pub added: Vec<TrackSummary>,
+    pub removed: Vec<TrackSummary>,
+    pub moved: Vec<TrackSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TrackSummary {
+    /// Spotify URI of the track, or a `local:` key for local files
+    pub id: String,
+    pub name: String,
+    pub artists: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PlaylistRename {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PlaylistDiff {
+    pub playlist: PlaylistSummary,
+    pub tracks: TracksDiff,
+}
+
+impl TrackSummary {
+    pub fn from_saved_track(saved: &SavedTrack) -> Self {
+        Self::from_track(&saved.track)
+    }
+
+    pub fn from_playable(item: &PlayableItem) -> Self {
+        match item {
+            PlayableItem::Track(track) => Self::from_track(track),
+            PlayableItem::Episode(episode) => Self {
+                id: playable_key(item),
+                name: episode.name.clone(),
+                artists: vec![episode.show.name.clone()],
+            },
+        }
+    }
+
+    fn from_track(track: &FullTrack) -> Self {
+        Self {
+            id: track_key(track),
+            name: track.name.clone(),
+            artists: track
+                .artists
+                .iter()
+                .map(|artist| artist.name.clone())
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for TrackSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.artists.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} — {}", self.name, self.artists.join(", "))
+        }
+    }
+}
+
+impl PlaylistSummary {
+    pub fn from_snapshot(snapshot: &PlaylistSnapshot) -> Self {
+        Self {
+            id: snapshot.playlist.id.id().to_string(),
+            name: snapshot.playlist.name.clone(),
+        }
+    }
+}
+
+impl TracksDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+
+    /// Compare two ordered track lists
+    ///
+    /// Duplicate tracks are paired up by occurrence. When `detect_moves` is set,
+    /// tracks that are in both lists but fall outside of the longest run of
+    /// tracks that kept their relative order are reported as moved.
+    pub fn between(
+        previous: &[TrackSummary],
+        current: &[TrackSummary],
+        detect_moves: bool,
+    ) -> Self {
+        let mut occurrences: HashMap<&str, VecDeque<usize>> = HashMap::new();
+        for (index, track) in current.iter().enumerate() {
+            occurrences.entry(&track.id).or_default().push_back(index);
+        }
+
+        let mut matches = vec![None; current.len()];
+        let mut removed = Vec::new();
+        for (previous_index, track) in previous.iter().enumerate() {
+            match occurrences
+                .get_mut(track.id.as_str())
+                .and_then(VecDeque::pop_front)
+            {
+                Some(current_index) => matches[current_index] = Some(previous_index),
+                None => removed.push(track.clone()),
+            }
+        }
+
+        let added = current
+            .iter()
+            .zip(&matches)
+            .filter(|(_, matched)| matched.is_none())
+            .map(|(track, _)| track.clone())
+            .collect();
+
+        let moved = if detect_moves {
+            let common: Vec<(usize, usize)> = matches
+                .iter()
+                .enumerate()
+                .filter_map(|(current_index, previous_index)| {
+                    previous_index.map(|previous_index| (current_index, previous_index))
+                })
+                .collect();
+
+            let previous_order: Vec<usize> = common.iter().map(|&(_, previous)| previous).collect();
+            let mut stationary = longest_increasing_subsequence(&previous_order).into_iter();
+            let mut next_stationary = stationary.next();
+
+            let mut moved = Vec::new();
+            for (position, &(current_index, _)) in common.iter().enumerate() {
+                if next_stationary == Some(position) {
+                    next_stationary = stationary.next();
+                } else {
+                    moved.push(current[current_index].clone());
+                }
+            }
+            moved
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            added,
+            removed,
+            moved,
+        }
+    }
+
+    fn counts(&self) -> String {
+        let mut counts = Vec::new();
+        if !self.added.is_empty() {
+            counts.push(format!("+{}", self.added.len()));
+        }
+        if !self.removed.is_empty() {
+            counts.push(format!("-{}", self.removed.len()));
+        }
+        if !self.moved.is_empty() {
+            counts.push(format!("{} moved", self.moved.len()));
+        }
+        counts.join(", ")
+    }
+
+    fn write_body(&self, body: &mut String) {
+        for (prefix, tracks) in [("+", &self.added), ("-", &self.removed), ("~", &self.moved)] {
+            for track in tracks.iter().take(BODY_TRACKS) {
+                let _ = writeln!(body, "{prefix} {track}");
+            }
+            if tracks.len() > BODY_TRACKS {
+                let _ = writeln!(body, "{prefix} … and {} more", tracks.len() - BODY_TRACKS);
+            }
+        }
+    }
+}
+
+impl SnapshotDiff {
+    pub fn between(previous: &Snapshot, current: &Snapshot) -> Self {
+        let liked_songs = TracksDiff::between(
+            &liked_song_summaries(previous),
+            &liked_song_summaries(current),
+            false,
+        );
+
+        let previous_playlists: HashMap<_, _> = previous
+            .playlists
+            .iter()
+            .map(|playlist| (playlist.playlist.id.id(), playlist))
+            .collect();
+        let current_ids: HashSet<_> = current
+            .playlists
+            .iter()
+            .map(|playlist| playlist.playlist.id.id())
+            .collect();
+
+        let mut diff = SnapshotDiff {
+            liked_songs,
+            ..Default::default()
+        };
+
+        for playlist in &current.playlists {
+            let Some(previous) = previous_playlists.get(playlist.playlist.id.id()) else {
+                diff.playlists_created
+                    .push(PlaylistSummary::from_snapshot(playlist));
+                continue;
+            };
+
+            if previous.playlist.name != playlist.playlist.name {
+                diff.playlists_renamed.push(PlaylistRename {
+                    id: playlist.playlist.id.id().to_string(),
+                    from: previous.playlist.name.clone(),
+                    to: playlist.playlist.name.clone(),
+                });
+            }
+
+            let tracks = TracksDiff::between(
+                &playlist_summaries(previous),
+                &playlist_summaries(playlist),
+                true,
+            );
+            if !tracks.is_empty() {
+                diff.playlists_modified.push(PlaylistDiff {
+                    playlist: PlaylistSummary::from_snapshot(playlist),
+                    tracks,
+                });
+            }
+        }
+
+        let previous_availability = availability(previous);
+        diff.became_unavailable = availability(current)
+            .into_iter()
+            .filter(|(key, (_, playable))| {
+                !playable
+                    && previous_availability
+                        .get(key)
+                        .is_some_and(|(_, was_playable)| *was_playable)
+            })
+            .map(|(_, (track, _))| track)
+            .collect();
+        diff.became_unavailable
+            .sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+        diff.playlists_deleted = previous
+            .playlists
+            .iter()
+            .filter(|playlist| !current_ids.contains(playlist.playlist.id.id()))
+            .map(PlaylistSummary::from_snapshot)
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.liked_songs.is_empty()
+            && self.playlists_created.is_empty()
+            && self.playlists_deleted.is_empty()
+            && self.playlists_renamed.is_empty()
+            && self.playlists_modified.is_empty()
+            && self.became_unavailable.is_empty()
+    }
+
+    /// Individual human readable changes, e.g. `+12 liked` or
+    /// `playlist 'Road Trip' renamed to 'Summer'`
+    pub fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if !self.liked_songs.added.is_empty() {
+            changes.push(format!("+{} liked", self.liked_songs.added.len()));
+        }
+        if !self.liked_songs.removed.is_empty() {
+            changes.push(format!("-{} liked", self.liked_songs.removed.len()));
+        }
+        for playlist in &self.playlists_created {
+            changes.push(format!("playlist '{}' created", playlist.name));
+        }
+        for playlist in &self.playlists_deleted {
+            changes.push(format!("playlist '{}' deleted", playlist.name));
+        }
+        for rename in &self.playlists_renamed {
+            changes.push(format!(
+                "playlist '{}' renamed to '{}'",
+                rename.from, rename.to
+            ));
+        }
+        for playlist in &self.playlists_modified {
+            changes.push(format!(
+                "playlist '{}' ({})",
+                playlist.playlist.name,
+                playlist.tracks.counts()
+            ));
+        }
+        if !self.became_unavailable.is_empty() {
+            changes.push(format!("{} unavailable", self.became_unavailable.len()));
+        }
+
+        changes
+    }
+
+    /// Single line summary of the changes
+    pub fn summary(&self) -> String {
+        let changes = self.changes();
+
+        if changes.is_empty() {
+            return String::from("no changes");
+        }
+
+        let mut summary = changes[..changes.len().min(SUBJECT_CHANGES)].join(", ");
+        if changes.len() > SUBJECT_CHANGES {
+            let _ = write!(
+                summary,
+                ", and {} more changes",
+                changes.len() - SUBJECT_CHANGES
+            );
+        }
+
+        summary
+    }
+
+    /// Commit message with the summary as the subject and the individual track
+    /// changes in the body
+    pub fn commit_message(&self) -> String {
+        let mut message = self.summary();
+        message.push('\n');
+
+        if !self.liked_songs.is_empty() {
+            message.push_str("\nLiked songs\n");
+            self.liked_songs.write_body(&mut message);
+        }
+
+        for playlist in &self.playlists_modified {
+            let _ = write!(message, "\nPlaylist '{}'\n", playlist.playlist.name);
+            playlist.tracks.write_body(&mut message);
+        }
+
+        if !self.became_unavailable.is_empty() {
+            message.push_str("\nNo longer available\n");
+            for track in self.became_unavailable.iter().take(BODY_TRACKS) {
+                let _ = writeln!(message, "! {track}");
+            }
+            if self.became_unavailable.len() > BODY_TRACKS {
+                let _ = writeln!(
+                    message,
+                    "! … and {} more",
+                    self.became_unavailable.len() - BODY_TRACKS
+                );
+            }
+        }
+
+        message
+    }
+}
+
+fn liked_song_summaries(snapshot: &Snapshot) -> Vec<TrackSummary> {
+    snapshot
+        .liked_songs
+        .iter()
+        .map(TrackSummary::from_saved_track)
+        .collect()
+}
+
+/// Every track in the library, keyed by [`TrackSummary::id`], with whether it
+/// is playable
+fn availability(snapshot: &Snapshot) -> HashMap<String, (TrackSummary, bool)> {
+    let liked_songs = snapshot.liked_songs.iter().map(|saved| {
+        (
+            TrackSummary::from_saved_track(saved),
+            is_playable(&saved.track),
+        )
+    });
+    let playlist_items = snapshot
+        .playlists
+        .iter()
+        .flat_map(|playlist| &playlist.items)
+        .filter_map(|item| item.track.as_ref())
+        .map(|item| (TrackSummary::from_playable(item), is_playable_item(item)));
+
+    liked_songs
+        .chain(playlist_items)
+        .map(|(track, playable)| (track.id.clone(), (track, playable)))
+        .collect()
+}
+
+fn playlist_summaries(playlist: &PlaylistSnapshot) -> Vec<TrackSummary> {
+    playlist
+        .items
+        .iter()
+        .filter_map(|item| item.track.as_ref())
+        .map(TrackSummary::from_playable)
+        .collect()
+}
+
+/// Indices of one longest strictly increasing subsequence of `values`, in order
+fn longest_increasing_subsequence(values: &[usize]) -> Vec<usize> {
+    let mut tails: Vec<usize> = Vec::new();
+    let mut predecessors = vec![None; values.len()];
+
+    for (index, &value) in values.iter().enumerate() {
+        let position = tails.partition_point(|&tail| values[tail] < value);
+
+        if position > 0 {
+            predecessors[index] = Some(tails[position - 1]);
+        }
+
+        if position == tails.len() {
+            tails.push(index);
+        } else {
+            tails[position] = index;
+        }
+    }
+
+    let mut subsequence = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        subsequence.push(index);
+        next = predecessors[index];
+    }
+    subsequence.reverse();
+
+    subsequence
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rspotify::model::{
+        FullTrack, SavedTrack, SimplifiedAlbum, SimplifiedArtist, TrackId, TrackLink,
+    };
+
+    use super::{longest_increasing_subsequence, Snapshot, SnapshotDiff, TrackSummary, TracksDiff};
+
+    fn track(id: &str) -> TrackSummary {
+        TrackSummary {
+            id: format!("spotify:track:{id}"),
+            name: id.to_uppercase(),
+            artists: vec![String::from("Artist")],
+        }
+    }
+
+    fn tracks(ids: &[&str]) -> Vec<TrackSummary> {
+        ids.iter().map(|id| track(id)).collect()
+    }
+
+    fn track_id(id: &str) -> TrackId<'static> {
+        TrackId::from_id(id.to_string()).expect("track id should be valid")
+    }
+
+    fn saved_track(id: &str, linked_from: Option<&str>, is_playable: Option<bool>) -> SavedTrack {
+        SavedTrack {
+            added_at: chrono::DateTime::default(),
+            track: FullTrack {
+                album: SimplifiedAlbum::default(),
+                artists: vec![SimplifiedArtist {
+                    name: String::from("Artist"),
+                    ..Default::default()
+                }],
+                available_markets: Vec::new(),
+                disc_number: 1,
+                duration: chrono::Duration::seconds(180),
+                explicit: false,
+                external_ids: HashMap::new(),
+                external_urls: HashMap::new(),
+                href: None,
+                id: Some(track_id(id)),
+                is_local: false,
+                is_playable,
+                linked_from: linked_from.map(|link| TrackLink {
+                    external_urls: HashMap::new(),
+                    href: String::new(),
+                    id: track_id(link),
+                }),
+                restrictions: None,
+                name: id.to_uppercase(),
+                popularity: 0,
+                preview_url: None,
+                track_number: 1,
+            },
+        }
+    }
+
+    fn liked_songs(liked_songs: Vec<SavedTrack>) -> Snapshot {
+        Snapshot {
+            liked_songs,
+            playlists: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn nothing_changed() {
+        let diff = TracksDiff::between(&tracks(&["a", "b", "c"]), &tracks(&["a", "b", "c"]), true);
+
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn added_and_removed() {
+        let diff = TracksDiff::between(&tracks(&["a", "b", "c"]), &tracks(&["a", "c", "d"]), true);
+
+        assert_eq!(diff.added, tracks(&["d"]));
+        assert_eq!(diff.removed, tracks(&["b"]));
+        assert!(diff.moved.is_empty());
+    }
+
+    #[test]
+    fn moved() {
+        let previous = tracks(&["a", "b", "c", "d"]);
+        let current = tracks(&["b", "c", "d", "a"]);
+
+        let diff = TracksDiff::between(&previous, &current, true);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.moved, tracks(&["a"]));
+
+        let diff = TracksDiff::between(&previous, &current, false);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn duplicates_are_paired_by_occurrence() {
+        let diff = TracksDiff::between(&tracks(&["a", "b", "a"]), &tracks(&["a", "b"]), true);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed, tracks(&["a"]));
+        assert!(diff.moved.is_empty());
+
+        let diff = TracksDiff::between(&tracks(&["a"]), &tracks(&["a", "a"]), true);
+        assert_eq!(diff.added, tracks(&["a"]));
+        assert!(diff.removed.is_empty());
+
+        let diff = TracksDiff::between(&tracks(&["a", "b", "a"]), &tracks(&["a", "a", "b"]), true);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.moved, tracks(&["a"]));
+    }
+
+    #[test]
+    fn longest_increasing_subsequence_indices() {
+        assert!(longest_increasing_subsequence(&[]).is_empty());
+        assert_eq!(longest_increasing_subsequence(&[0, 1, 2]), vec![0, 1, 2]);
+        assert_eq!(longest_increasing_subsequence(&[2, 1, 0]).len(), 1);
+        assert_eq!(
+            longest_increasing_subsequence(&[3, 1, 2, 5, 4]),
+            vec![1, 2, 4]
+        );
+    }
+
+    #[test]
+    fn relinked_tracks_are_unchanged() {
+        let previous = liked_songs(vec![saved_track("original", None, None)]);
+        let current = liked_songs(vec![saved_track("relinked", Some("original"), Some(true))]);
+
+        let diff = SnapshotDiff::between(&previous, &current);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary(), "no changes");
+    }
+
+    #[test]
+    fn became_unavailable() {
+        let previous = liked_songs(vec![
+            saved_track("playable", None, Some(true)),
+            saved_track("unknown", None, None),
+            saved_track("unavailable", None, Some(false)),
+        ]);
+        let current = liked_songs(vec![
+            saved_track("playable", None, Some(false)),
+            saved_track("unknown", None, Some(false)),
+            saved_track("unavailable", None, Some(false)),
+            saved_track("added", None, Some(false)),
+        ]);
+
+        let diff = SnapshotDiff::between(&previous, &current);
+
+        let names: Vec<_> = diff
+            .became_unavailable
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        // Tracks fetched without a market were assumed playable
+        assert_eq!(names, ["PLAYABLE", "UNKNOWN"]);
+        assert_eq!(diff.liked_songs.added.len(), 1);
+        assert!(diff.changes().contains(&String::from("2 unavailable")));
+    }
+
+    #[test]
+    fn vanished_tracks_are_removed() {
+        let previous = liked_songs(vec![
+            saved_track("kept", None, Some(true)),
+            saved_track("vanished", None, Some(true)),
+        ]);
+        let current = liked_songs(vec![saved_track("kept", None, Some(true))]);
+
+        let diff = SnapshotDiff::between(&previous, &current);
+
+        assert!(diff.became_unavailable.is_empty());
+        let removed: Vec<_> = diff
+            .liked_songs
+            .removed
+            .iter()
+            .map(|track| track.name.as_str())
+            .collect();
+        assert_eq!(removed, ["VANISHED"]);
+    }
+}
@@ -0,0 +1,444 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use axum::http::StatusCode;
+use futures::{StreamExt, TryStreamExt};
+use octocrab::{models::InstallationToken, Octocrab};
+use once_cell::sync::Lazy;
+use rspotify::model::PlaylistId;
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+use tracing::error_span;
+
+use crate::{
+    environment::GITHUB_ENVIRONMENT, internal_server_error, metrics, pages::InternalServerError,
+    router::authentication::github::GithubInstallation,
+};
+
+use super::snapshot::{
+    is_playlist_file, playlist_file, PlaylistSnapshot, Snapshot, LIKED_SONGS_FILE,
+    PLAYLISTS_DIRECTORY,
+};
+
+/// Number of files that are downloaded at once when reading a snapshot
+const DOWNLOAD_CONCURRENCY: usize = 8;
+
+/// Number of snapshots kept in memory, a snapshot of a large library takes
+/// a few megabytes
+const SNAPSHOT_CACHE_SIZE: usize = 8;
+
+/// Recently read snapshots by repository and full commit sha, most recently
+/// used first. Commits never change, so viewing or comparing the same
+/// snapshots again does not download every file again.
+static SNAPSHOT_CACHE: Lazy<Mutex<VecDeque<(String, Snapshot)>>> = Lazy::new(Default::default);
+
+/// Number of commits listed per page of history
+pub const HISTORY_PAGE_SIZE: u8 = 30;
+
+const README: &str = "# Spotify Backup\n\nThis repository is managed by spotify-backup.\n";
+
+/// Message of the commit that adds the readme to an empty repository
+const INITIAL_COMMIT_MESSAGE: &str = "Initialize spotify backup";
+
+/// A github repository that snapshots are committed to
+pub struct BackupRepository {
+    client: Octocrab,
+    owner: String,
+    name: String,
+}
+
+/// A commit in the history of the backup repository
+#[derive(Debug, Clone)]
+pub struct SnapshotCommit {
+    pub sha: String,
+    pub message: String,
+    pub date: Option<OffsetDateTime>,
+}
+
+impl SnapshotCommit {
+    /// First line of the commit message
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// Whether the string is a full commit sha, and therefore safe to
+/// interpolate into api routes. Abbreviated shas are not accepted, they could
+/// become ambiguous as the history grows.
+pub fn is_commit_sha(sha: &str) -> bool {
+    sha.len() == 40 && sha.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// The tip of the branch that snapshots are committed to
+#[derive(Debug, Clone)]
+pub struct Head {
+    pub branch: String,
+    pub commit: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitTree {
+    tree: Vec<GitTreeEntry>,
+    truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitTreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+impl BackupRepository {
+    /// Access the repository with a new installation token of the github
+    /// app, that can only write the contents of this one repository
+    #[tracing::instrument(skip_all, fields(installation = installation.id.0, repository = installation.repository.0))]
+    pub async fn for_installation(
+        installation: &GithubInstallation,
+    ) -> Result<Self, InternalServerError> {
+        let token: InstallationToken = InternalServerError::wrap(
+            metrics::github_request(GITHUB_ENVIRONMENT.client.post(
+                format!("/app/installations/{}/access_tokens", installation.id),
+                Some(&json!({
+                    "repository_ids": [installation.repository],
+                    "permissions": {
+                        "contents": "write",
+                        "metadata": "read",
+                    },
+                })),
+            )),
+            error_span!("creating installation access token"),
+        )
+        .await?;
+
+        let Some(repository) = token.repositories.into_iter().flatten().next() else {
+            return Err(internal_server_error!(
+                "installation access token does not grant access to the repository"
+            ));
+        };
+        let Some(owner) = repository.owner else {
+            return Err(internal_server_error!(
+                "repository of installation has no owner",
+                repository = repository.id.0
+            ));
+        };
+
+        let client = Octocrab::builder()
+            .personal_token(token.token)
+            .build()
+            .map_err(InternalServerError::from_error)?;
+
+        Ok(Self {
+            client,
+            owner: owner.login,
+            name: repository.name,
+        })
+    }
+
+    fn route(&self, path: &str) -> String {
+        format!("/repos/{}/{}/{path}", self.owner, self.name)
+    }
+
+    /// Find the tip of the default branch, initializing the repository if it
+    /// is still empty
+    #[tracing::instrument(skip(self), fields(repository = %self))]
+    pub async fn head(&self) -> Result<Head, InternalServerError> {
+        let repository = InternalServerError::wrap(
+            metrics::github_request(self.client.repos(&self.owner, &self.name).get()),
+            error_span!("fetching repository"),
+        )
+        .await?;
+        let branch = repository.default_branch.unwrap_or_else(|| "main".into());
+
+        if let Some(commit) = self.branch_commit(&branch).await? {
+            return Ok(Head { branch, commit });
+        }
+
+        // The git database api is unavailable for empty repositories, the
+        // contents api is able to create the first commit
+        InternalServerError::wrap(
+            metrics::github_request(
+                self.client
+                    .repos(&self.owner, &self.name)
+                    .create_file("README.md", INITIAL_COMMIT_MESSAGE, README)
+                    .branch(&branch)
+                    .send(),
+            ),
+            error_span!("initializing empty repository"),
+        )
+        .await?;
+
+        match self.branch_commit(&branch).await? {
+            Some(commit) => Ok(Head { branch, commit }),
+            None => Err(internal_server_error!(
+                "repository is still empty after initialization",
+                branch
+            )),
+        }
+    }
+
+    async fn branch_commit(&self, branch: &str) -> Result<Option<String>, InternalServerError> {
+        let response = InternalServerError::wrap(
+            metrics::github_request(
+                self.client
+                    ._get(self.route(&format!("git/ref/heads/{branch}"))),
+            ),
+            error_span!("fetching branch reference", branch),
+        )
+        .await?;
+
+        if response.status() == StatusCode::CONFLICT {
+            // Git Repository is empty
+            return Ok(None);
+        }
+
+        let reference = InternalServerError::wrap(
+            async {
+                let response = octocrab::map_github_error(response).await?;
+                self.client.body_to_string(response).await
+            },
+            error_span!("receiving branch reference", branch),
+        )
+        .await?;
+
+        let reference: octocrab::models::repos::Ref =
+            serde_json::from_str(&reference).map_err(InternalServerError::from_error)?;
+
+        match reference.object {
+            octocrab::models::repos::Object::Commit { sha, .. } => Ok(Some(sha)),
+            object => Err(internal_server_error!(
+                "branch does not point to a commit",
+                branch,
+                ?object
+            )),
+        }
+    }
+
+    /// Entries directly in a tree, a commit sha lists its root tree
+    async fn tree(&self, sha: &str) -> Result<Vec<GitTreeEntry>, InternalServerError> {
+        let tree: GitTree = InternalServerError::wrap(
+            metrics::github_request(
+                self.client
+                    .get(self.route(&format!("git/trees/{sha}")), None::<&()>),
+            ),
+            error_span!("fetching tree", sha),
+        )
+        .await?;
+
+        // A partial listing would make missing files look deleted
+        if tree.truncated {
+            return Err(internal_server_error!("tree was truncated", sha));
+        }
+
+        Ok(tree.tree)
+    }
+
+    /// Paths of the files in the root and the playlists directory of a
+    /// commit, the directories are listed one by one since github truncates
+    /// recursive listings of large trees
+    async fn files_at(&self, commit: &str) -> Result<Vec<String>, InternalServerError> {
+        let mut files = Vec::new();
+
+        for entry in self.tree(commit).await? {
+            match entry.kind.as_str() {
+                "blob" => files.push(entry.path),
+                "tree" if entry.path == PLAYLISTS_DIRECTORY => files.extend(
+                    self.tree(&entry.sha)
+                        .await?
+                        .into_iter()
+                        .filter(|playlist| playlist.kind == "blob")
+                        .map(|playlist| format!("{PLAYLISTS_DIRECTORY}/{}", playlist.path)),
+                ),
+                _ => {}
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Snapshot commits on the default branch, newest first. A page can have
+    /// fewer than [`HISTORY_PAGE_SIZE`] commits only if it is the last one.
+    #[tracing::instrument(skip(self), fields(repository = %self))]
+    pub async fn history(&self, page: u32) -> Result<Vec<SnapshotCommit>, InternalServerError> {
+        let commits = InternalServerError::wrap(
+            metrics::github_request(
+                self.client
+                    .repos(&self.owner, &self.name)
+                    .list_commits()
+                    .per_page(HISTORY_PAGE_SIZE)
+                    .page(page)
+                    .send(),
+            ),
+            error_span!("listing commits", page),
+        )
+        .await?;
+
+        Ok(commits
+            .items
+            .into_iter()
+            // The commit that initialized the repository contains no snapshot
+            .filter(|commit| {
+                !(commit.parents.is_empty() && commit.commit.message == INITIAL_COMMIT_MESSAGE)
+            })
+            .map(|commit| SnapshotCommit {
+                date: commit
+                    .commit
+                    .committer
+                    .and_then(|committer| committer.date)
+                    .and_then(|date| OffsetDateTime::from_unix_timestamp(date.timestamp()).ok()),
+                message: commit.commit.message,
+                sha: commit.sha,
+            })
+            .collect())
+    }
+
+    async fn file_at(&self, commit: &str, path: &str) -> Result<String, InternalServerError> {
+        InternalServerError::wrap(
+            async {
+                let response = metrics::github_request(
+                    self.client
+                        .repos(&self.owner, &self.name)
+                        .raw_file(commit.to_string(), path),
+                )
+                .await?;
+                let response = octocrab::map_github_error(response).await?;
+                self.client.body_to_string(response).await
+            },
+            error_span!("downloading file", commit, path),
+        )
+        .await
+    }
+
+    #[tracing::instrument(skip(self), fields(repository = %self))]
+    pub async fn snapshot_at(&self, commit: &str) -> Result<Snapshot, InternalServerError> {
+        // Anything but a full sha could name a branch, which does change
+        let key = is_commit_sha(commit).then(|| format!("{self}@{commit}"));
+
+        if let Some(key) = &key {
+            let mut cache = SNAPSHOT_CACHE
+                .lock()
+                .expect("snapshot cache lock should not be poisoned");
+            let cached = cache
+                .iter()
+                .position(|(cached, _)| cached == key)
+                .and_then(|position| cache.remove(position));
+            if let Some((key, snapshot)) = cached {
+                cache.push_front((key, snapshot.clone()));
+
+                return Ok(snapshot);
+            }
+        }
+
+        let files = self
+            .files_at(commit)
+            .await?
+            .into_iter()
+            .filter(|path| path == LIKED_SONGS_FILE || is_playlist_file(path));
+
+        let files: Vec<(String, String)> = futures::stream::iter(files)
+            .map(|path| async move {
+                let contents = self.file_at(commit, &path).await?;
+
+                Ok::<_, InternalServerError>((path, contents))
+            })
+            .buffer_unordered(DOWNLOAD_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let snapshot = Snapshot::from_files(files)?;
+
+        if let Some(key) = key {
+            let mut cache = SNAPSHOT_CACHE
+                .lock()
+                .expect("snapshot cache lock should not be poisoned");
+            cache.retain(|(cached, _)| *cached != key);
+            cache.push_front((key, snapshot.clone()));
+            cache.truncate(SNAPSHOT_CACHE_SIZE);
+        }
+
+        Ok(snapshot)
+    }
+
+    #[tracing::instrument(skip(self), fields(repository = %self))]
+    pub async fn playlist_at(
+        &self,
+        commit: &str,
+        playlist: PlaylistId<'_>,
+    ) -> Result<PlaylistSnapshot, InternalServerError> {
+        let contents = self
+            .file_at(commit, &playlist_file(playlist.as_ref()))
+            .await?;
+
+        serde_json::from_str(&contents).map_err(InternalServerError::from_error)
+    }
+
+    /// Replace the snapshot files on the branch with a new commit, returning
+    /// the sha of the commit
+    #[tracing::instrument(skip(self, snapshot, message), fields(repository = %self))]
+    pub async fn commit_snapshot(
+        &self,
+        head: &Head,
+        snapshot: &Snapshot,
+        message: &str,
+    ) -> Result<String, InternalServerError> {
+        let files = snapshot.to_files()?;
+
+        let stale_files = self
+            .files_at(&head.commit)
+            .await?
+            .into_iter()
+            .filter(|path| is_playlist_file(path) && !files.contains_key(path));
+
+        let tree: Vec<_> = files
+            .iter()
+            .map(|(path, contents)| {
+                json!({ "path": path, "mode": "100644", "type": "blob", "content": contents })
+            })
+            .chain(stale_files.map(|path| {
+                // A null sha removes the file from the base tree
+                json!({ "path": path, "mode": "100644", "type": "blob", "sha": null })
+            }))
+            .collect();
+
+        let tree: GitObject = InternalServerError::wrap(
+            metrics::github_request(self.client.post(
+                self.route("git/trees"),
+                Some(&json!({ "base_tree": head.commit, "tree": tree })),
+            )),
+            error_span!("creating tree"),
+        )
+        .await?;
+
+        let commit: GitObject = InternalServerError::wrap(
+            metrics::github_request(self.client.post(
+                self.route("git/commits"),
+                Some(&json!({ "message": message, "tree": tree.sha, "parents": [head.commit] })),
+            )),
+            error_span!("creating commit", tree = tree.sha),
+        )
+        .await?;
+
+        let _: octocrab::models::repos::Ref = InternalServerError::wrap(
+            metrics::github_request(self.client.patch(
+                self.route(&format!("git/refs/heads/{}", head.branch)),
+                Some(&json!({ "sha": commit.sha })),
+            )),
+            error_span!("updating branch", branch = head.branch, commit = commit.sha),
+        )
+        .await?;
+
+        Ok(commit.sha)
+    }
+}
+
+impl std::fmt::Display for BackupRepository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use futures::Future;
+use rspotify::{
+    clients::{BaseClient, OAuthClient},
+    model::{
+        FullTrack, Market, Page, PlayableItem, PlaylistId, PlaylistItem, SavedTrack,
+        SimplifiedPlaylist,
+    },
+    prelude::Id,
+    AuthCodeSpotify, ClientResult,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::{error_span, Instrument};
+use utoipa::ToSchema;
+
+use crate::{metrics, pages::InternalServerError};
+
+pub const LIKED_SONGS_FILE: &str = "liked_songs.json";
+pub const PLAYLISTS_DIRECTORY: &str = "playlists";
+
+/// Maximum page size of the spotify playlist items endpoint
+const PLAYLIST_ITEMS_CHUNKS: u32 = 100;
+
+/// The complete state of a user's library at one point in time
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Snapshot {
+    /// Saved track objects as returned by the spotify web api
+    #[schema(value_type = Vec<Object>)]
+    pub liked_songs: Vec<SavedTrack>,
+    pub playlists: Vec<PlaylistSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlaylistSnapshot {
+    /// Simplified playlist object as returned by the spotify web api
+    #[schema(value_type = Object)]
+    pub playlist: SimplifiedPlaylist,
+    /// Playlist track objects as returned by the spotify web api
+    #[schema(value_type = Vec<Object>)]
+    pub items: Vec<PlaylistItem>,
+}
+
+impl Snapshot {
+    #[tracing::instrument(skip_all)]
+    pub async fn fetch(spotify: &AuthCodeSpotify) -> Result<Self, InternalServerError> {
+        let liked_songs = fetch_all_pages(
+            "liked_songs",
+            rspotify::DEFAULT_PAGINATION_CHUNKS,
+            |offset| {
+                spotify.current_user_saved_tracks_manual(
+                    Some(Market::FromToken),
+                    Some(rspotify::DEFAULT_PAGINATION_CHUNKS),
+                    Some(offset),
+                )
+            },
+        )
+        .await?;
+
+        let playlists =
+            fetch_all_pages("playlists", rspotify::DEFAULT_PAGINATION_CHUNKS, |offset| {
+                spotify.current_user_playlists_manual(
+                    Some(rspotify::DEFAULT_PAGINATION_CHUNKS),
+                    Some(offset),
+                )
+            })
+            .await?;
+
+        let mut playlist_snapshots = Vec::with_capacity(playlists.len());
+        for playlist in playlists {
+            let items = fetch_all_pages("playlist_items", PLAYLIST_ITEMS_CHUNKS, |offset| {
+                spotify.playlist_items_manual(
+                    playlist.id.as_ref(),
+                    None,
+                    Some(Market::FromToken),
+                    Some(PLAYLIST_ITEMS_CHUNKS),
+                    Some(offset),
+                )
+            })
+            .instrument(error_span!("fetch_playlist", playlist = playlist.id.id()))
+            .await?;
+
+            playlist_snapshots.push(PlaylistSnapshot { playlist, items });
+        }
+
+        Ok(Snapshot {
+            liked_songs,
+            playlists: playlist_snapshots,
+        })
+    }
+
+    /// Lay the snapshot out as the files that are stored in the backup destination
+    pub fn to_files(&self) -> Result<BTreeMap<String, String>, InternalServerError> {
+        let mut files = BTreeMap::new();
+
+        files.insert(
+            LIKED_SONGS_FILE.to_string(),
+            serde_json::to_string_pretty(&self.liked_songs)
+                .map_err(InternalServerError::from_error)?,
+        );
+
+        for playlist in &self.playlists {
+            files.insert(
+                playlist_file(playlist.playlist.id.as_ref()),
+                serde_json::to_string_pretty(playlist).map_err(InternalServerError::from_error)?,
+            );
+        }
+
+        Ok(files)
+    }
+
+    /// Inverse of [`Snapshot::to_files`], unknown files are ignored
+    pub fn from_files(
+        files: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, InternalServerError> {
+        let mut snapshot = Snapshot::default();
+
+        for (path, contents) in files {
+            if path == LIKED_SONGS_FILE {
+                snapshot.liked_songs =
+                    error_span!("deserializing liked songs", path).in_scope(|| {
+                        serde_json::from_str(&contents).map_err(InternalServerError::from_error)
+                    })?;
+            } else if is_playlist_file(&path) {
+                snapshot
+                    .playlists
+                    .push(error_span!("deserializing playlist", path).in_scope(|| {
+                        serde_json::from_str(&contents).map_err(InternalServerError::from_error)
+                    })?);
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+pub fn playlist_file(playlist: PlaylistId<'_>) -> String {
+    format!("{PLAYLISTS_DIRECTORY}/{}.json", playlist.id())
+}
+
+pub fn is_playlist_file(path: &str) -> bool {
+    path.strip_prefix(PLAYLISTS_DIRECTORY)
+        .and_then(|path| path.strip_prefix('/'))
+        .is_some_and(|name| name.ends_with(".json") && !name.contains('/'))
+}
+
+/// Stable identifier for a playable item, local files have no spotify id so
+/// their name is used instead
+pub fn playable_key(item: &PlayableItem) -> String {
+    match item {
+        PlayableItem::Track(track) => track_key(track),
+        PlayableItem::Episode(episode) => episode.id.uri(),
+    }
+}
+
+/// Tracks that spotify relinked to another release in the user's market are
+/// keyed by the originally requested track
+pub fn track_key(track: &FullTrack) -> String {
+    match (&track.linked_from, &track.id) {
+        (Some(link), _) => link.id.uri(),
+        (None, Some(id)) => id.uri(),
+        (None, None) => format!("local:{}", track.name),
+    }
+}
+
+/// Whether the track can be played in the market of the user, tracks fetched
+/// without a market have no playability information and are assumed playable
+pub fn is_playable(track: &FullTrack) -> bool {
+    track.is_playable != Some(false)
+}
+
+pub fn is_playable_item(item: &PlayableItem) -> bool {
+    match item {
+        PlayableItem::Track(track) => is_playable(track),
+        PlayableItem::Episode(episode) => episode.is_playable,
+    }
+}
+
+async fn fetch_all_pages<T, F, Fut>(
+    name: &'static str,
+    page_size: u32,
+    mut fetch_page: F,
+) -> Result<Vec<T>, InternalServerError>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = ClientResult<Page<T>>>,
+{
+    let mut downloaded = 0;
+    let mut items = Vec::new();
+
+    loop {
+        let start = Instant::now();
+        let mut page = InternalServerError::wrap(
+            metrics::spotify_request(fetch_page(downloaded)),
+            error_span!("fetch_page", name, offset = downloaded),
+        )
+        .await?;
+
+        let received = page.items.len() as u32;
+        downloaded += received;
+        let total = page.total;
+
+        tracing::trace!(
+            name,
+            request = format_args!(
+                "{}/{}",
+                downloaded.div_ceil(page_size),
+                total.div_ceil(page_size)
+            ),
+            item = format_args!("{downloaded}/{total}"),
+            elapsed = ?start.elapsed()
+        );
+
+        items.append(&mut page.items);
+
+        if page.next.is_none() || received == 0 {
+            break;
+        }
+    }
+
+    Ok(items)
+}
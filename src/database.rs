@@ -2,7 +2,7 @@ use std::{env, fmt::Debug};
 
 use entity::{account, github_auth, prelude::*, spotify_auth, user_session};
 use futures::{Stream, StreamExt};
-use migration::{IntoIden, Migrator, MigratorTrait, OnConflict};
+use migration::{Expr, IntoIden, Migrator, MigratorTrait, OnConflict};
 use rspotify::prelude::Id;
 use sea_orm::{
     prelude::*, sea_query, ConnectOptions, DatabaseTransaction, DeleteResult, FromQueryResult,
@@ -15,13 +15,27 @@ use crate::{
     internal_server_error,
     pages::InternalServerError,
     router::authentication::{
-        self, github::GithubAuthentication, spotify::SpotifyAuthentication, User,
+        self,
+        github::{GithubAuthentication, GithubInstallation},
+        spotify::SpotifyAuthentication,
+        User,
     },
 };
 
+pub use self::export::AccountRows;
+
 use self::id::{AccountId, UserSessionId};
 
+mod account_deletion;
+mod api_token;
+mod backup_run;
+mod export;
+mod feed;
 pub mod id;
+mod notification_settings;
+mod session;
+mod token_encryption;
+mod webhook;
 
 #[derive(Debug, Clone)]
 pub struct Database {
@@ -44,25 +58,88 @@ impl Database {
 
         Ok(Database { connection })
     }
+
+    /// Check that the database is reachable, errors are returned as is since
+    /// health checks are expected to fail while the database is down
+    pub async fn ping(&self) -> Result<(), DbErr> {
+        self.connection.ping().await
+    }
+
+    /// Number of migrations that have not been applied to the database
+    pub async fn pending_migrations(&self) -> Result<usize, DbErr> {
+        Ok(Migrator::get_pending_migrations(&self.connection)
+            .await?
+            .len())
+    }
+
+    /// Open and idle connections of the connection pool
+    pub fn pool_connections(&self) -> (u32, usize) {
+        let pool = self.connection.get_postgres_connection_pool();
+
+        (pool.size(), pool.num_idle())
+    }
 }
 
-impl FromQueryResult for authentication::Account {
-    fn from_query_result(res: &QueryResult, pre: &str) -> Result<Self, DbErr> {
-        let account = account::Model::from_query_result(res, account::Entity.table_name())?;
-        let spotify =
-            spotify_auth::Model::from_query_result(res, spotify_auth::Entity.table_name())?;
-        let github =
-            github_auth::Model::from_query_result_optional(res, github_auth::Entity.table_name())?;
+/// The rows of an account, before its tokens are decrypted
+struct AccountModels {
+    account: account::Model,
+    spotify: spotify_auth::Model,
+    github: Option<github_auth::Model>,
+}
+
+impl FromQueryResult for AccountModels {
+    fn from_query_result(res: &QueryResult, _pre: &str) -> Result<Self, DbErr> {
+        Ok(AccountModels {
+            account: account::Model::from_query_result(res, account::Entity.table_name())?,
+            spotify: spotify_auth::Model::from_query_result(
+                res,
+                spotify_auth::Entity.table_name(),
+            )?,
+            github: github_auth::Model::from_query_result_optional(
+                res,
+                github_auth::Entity.table_name(),
+            )?,
+        })
+    }
+}
+
+impl AccountModels {
+    fn into_account(self) -> Result<authentication::Account, DbErr> {
+        let AccountModels {
+            account,
+            spotify,
+            github,
+        } = self;
+
+        let decryption_error = |error: InternalServerError| {
+            DbErr::Custom(format!(
+                "tokens of account {} could not be decrypted: {}",
+                account.id,
+                error.inner_error()
+            ))
+        };
+        let spotify = SpotifyAuthentication::from_model(spotify).map_err(decryption_error)?;
+        let github = github
+            .map(GithubAuthentication::from_model)
+            .transpose()
+            .map_err(decryption_error)?;
 
         Ok(authentication::Account {
             created_at: account.created_at,
+            deletion_scheduled_at: account.deletion_scheduled_at,
             id: AccountId::from_model(account),
-            spotify: SpotifyAuthentication::from_model(spotify),
-            github: github.map(GithubAuthentication::from_model),
+            spotify,
+            github,
         })
     }
 }
 
+impl FromQueryResult for authentication::Account {
+    fn from_query_result(res: &QueryResult, pre: &str) -> Result<Self, DbErr> {
+        AccountModels::from_query_result(res, pre)?.into_account()
+    }
+}
+
 pub trait PrefixExt {
     fn add_columns<T: EntityTrait>(self, entity: T) -> Self;
 }
@@ -95,7 +172,9 @@ impl Database {
             .add_columns(SpotifyAuth)
             .left_join(GithubAuth)
             .add_columns(GithubAuth)
-            .into_model::<authentication::Account>()
+            // Decrypted one by one, so an account with undecryptable tokens
+            // does not fail the whole page
+            .into_model::<AccountModels>()
             .paginate(&self.connection, page_size);
 
         // FIXME:
@@ -106,13 +185,37 @@ impl Database {
             }
         })
         .flat_map(|vec| match vec {
-            Ok(vec) => futures::stream::iter(vec.into_iter().map(Ok)).boxed(),
+            Ok(vec) => {
+                futures::stream::iter(vec.into_iter().map(AccountModels::into_account)).boxed()
+            }
             Err(err) => futures::stream::once(async { Err(err) }).boxed(),
         })
     }
 }
 
 impl Database {
+    /// The account with its authentications, for requests that are not made
+    /// with a user session
+    #[tracing::instrument(skip(self))]
+    pub async fn get_account(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<authentication::Account>, InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::find()
+                .select_only()
+                .add_columns(Account)
+                .left_join(SpotifyAuth)
+                .add_columns(SpotifyAuth)
+                .left_join(GithubAuth)
+                .add_columns(GithubAuth)
+                .filter(account::Column::Id.eq(account.into_uuid()))
+                .into_model::<authentication::Account>()
+                .one(&self.connection),
+        )
+        .await
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn get_current_user(
         &self,
@@ -142,11 +245,12 @@ impl Database {
                 session,
                 account: authentication::Account {
                     created_at: account.created_at,
+                    deletion_scheduled_at: account.deletion_scheduled_at,
                     id: AccountId::from_model(account),
 
-                    spotify: SpotifyAuthentication::from_model(spotify),
+                    spotify: SpotifyAuthentication::from_model(spotify)?,
 
-                    github: github.map(GithubAuthentication::from_model),
+                    github: github.map(GithubAuthentication::from_model).transpose()?,
                 },
             }));
         }
@@ -174,16 +278,17 @@ impl Database {
     #[tracing::instrument(skip_all, fields(account = ?user.account.id))]
     pub async fn delete_current_user(
         &self,
-        user: User,
+        user: &User,
     ) -> Result<crate::router::session::UserSession, InternalServerError> {
+        let spotify_id = user.account.spotify.user_id.id().to_string();
+
         let transaction = self
             .connection
             .transaction(|transaction| {
                 Box::pin(async move {
                     // Deleting the spotify authentication will cascade to the user account
                     let DeleteResult { rows_affected } = InternalServerError::wrap(
-                        SpotifyAuth::delete(user.account.spotify.into_model().into_active_model())
-                            .exec(transaction),
+                        SpotifyAuth::delete_by_id(spotify_id).exec(transaction),
                         error_span!("deleting user"),
                     )
                     .await?;
@@ -205,6 +310,78 @@ impl Database {
     }
 }
 
+impl Database {
+    /// Remember the `state` of an OAuth flow started by this session
+    #[tracing::instrument(skip(self, session, state))]
+    pub async fn set_oauth_state(
+        &self,
+        session: UserSessionId,
+        state: &str,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            UserSession::update_many()
+                .filter(user_session::Column::Id.eq(session.into_uuid()))
+                .col_expr(user_session::Column::OauthState, Expr::value(state))
+                .col_expr(
+                    user_session::Column::OauthStateCreatedAt,
+                    Expr::value(OffsetDateTime::now_utc()),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove and return the `state` of the OAuth flow started by this
+    /// session, and when it was started, so that every state is single use
+    #[tracing::instrument(skip(self, session))]
+    pub async fn take_oauth_state(
+        &self,
+        session: UserSessionId,
+    ) -> Result<Option<(String, OffsetDateTime)>, InternalServerError> {
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    let session = InternalServerError::wrap(
+                        UserSession::find_by_id(session.into_uuid())
+                            .lock_exclusive()
+                            .one(transaction),
+                        error_span!("finding user session"),
+                    )
+                    .await?;
+
+                    let Some(session) = session else {
+                        return Ok(None);
+                    };
+
+                    InternalServerError::wrap(
+                        UserSession::update_many()
+                            .filter(user_session::Column::Id.eq(session.id))
+                            .col_expr(
+                                user_session::Column::OauthState,
+                                Expr::value(None::<String>),
+                            )
+                            .col_expr(
+                                user_session::Column::OauthStateCreatedAt,
+                                Expr::value(None::<OffsetDateTime>),
+                            )
+                            .exec(transaction),
+                        error_span!("clearing oauth state"),
+                    )
+                    .await?;
+
+                    Ok(session.oauth_state.zip(session.oauth_state_created_at))
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })
+    }
+}
+
 #[tracing::instrument(skip(connection))]
 async fn get_user_session(
     connection: &impl ConnectionTrait,
@@ -248,11 +425,52 @@ async fn logout_user_session(
     Ok(())
 }
 
+/// Replace the github account linked to an account, the github account
+/// must not be linked to any other account
+#[tracing::instrument(skip_all, fields(github = github_auth.user_id, account = ?github_auth.account))]
+async fn save_github_auth(
+    transaction: &DatabaseTransaction,
+    github_auth: github_auth::Model,
+) -> Result<(), InternalServerError> {
+    // An account can only have one github account linked
+    InternalServerError::wrap(
+        GithubAuth::delete_many()
+            .filter(github_auth::Column::Account.eq(github_auth.account))
+            .filter(github_auth::Column::UserId.ne(github_auth.user_id.clone()))
+            .exec(transaction),
+        error_span!("unlinking previous github account"),
+    )
+    .await?;
+
+    InternalServerError::wrap(
+        GithubAuth::insert(github_auth.into_active_model())
+            .on_conflict(
+                OnConflict::column(github_auth::Column::UserId)
+                    // Do not update created_at
+                    .update_columns([
+                        github_auth::Column::AccessToken,
+                        github_auth::Column::ExpiresAt,
+                        github_auth::Column::RefreshToken,
+                        github_auth::Column::RefreshTokenExpiresAt,
+                        github_auth::Column::TokenKeyId,
+                        github_auth::Column::TokenDataKey,
+                    ])
+                    .to_owned(),
+            )
+            .exec(transaction),
+        error_span!("updating saved github authentication details"),
+    )
+    .await?;
+
+    Ok(())
+}
+
 impl Database {
-    #[tracing::instrument(skip(self, spotify_auth), fields(spotify = spotify_auth.user_id.id()))]
+    #[tracing::instrument(skip(self, client, spotify_auth), fields(spotify = spotify_auth.user_id.id()))]
     pub async fn login_user(
         &self,
         user_session: Option<crate::router::session::UserSession>,
+        client: crate::router::session::SessionClient,
         spotify_auth: SpotifyAuthentication,
     ) -> Result<UserSessionId, InternalServerError> {
         self.connection
@@ -271,6 +489,8 @@ impl Database {
                                         spotify_auth::Column::AccessToken,
                                         spotify_auth::Column::ExpiresAt,
                                         spotify_auth::Column::RefreshToken,
+                                        spotify_auth::Column::TokenKeyId,
+                                        spotify_auth::Column::TokenDataKey,
                                     ])
                                     .to_owned(),
                             )
@@ -297,6 +517,9 @@ impl Database {
                                         id: Uuid::new_v4(),
                                         spotify: spotify_id.clone(),
                                         created_at: OffsetDateTime::now_utc(),
+                                        webhook_secret: None,
+                                        feed_token: None,
+                                        deletion_scheduled_at: None,
                                     }
                                     .into_active_model(),
                                 )
@@ -307,7 +530,6 @@ impl Database {
                         }
                     };
 
-                    // TODO: session pruning periodically
                     let new_session = InternalServerError::wrap(
                         UserSession::insert(
                             user_session::Model {
@@ -316,6 +538,10 @@ impl Database {
                                 id: user_session
                                     .map_or_else(Uuid::new_v4, |session| session.id.into_uuid()),
                                 account: account.id,
+                                oauth_state: None,
+                                oauth_state_created_at: None,
+                                user_agent: client.user_agent,
+                                ip_address: client.ip_address,
                             }
                             .into_active_model(),
                         )
@@ -339,6 +565,92 @@ impl Database {
             })
     }
 
+    /// Replace the spotify account of the user with another one, unless that
+    /// spotify account already belongs to a different account
+    #[tracing::instrument(skip_all, fields(spotify = spotify_auth.user_id.id(), user = ?user.account.id))]
+    pub async fn link_spotify_to_account(
+        &self,
+        user: User,
+        spotify_auth: SpotifyAuthentication,
+    ) -> Result<Result<(), SpotifyAccountAlreadyTakenError>, InternalServerError> {
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    let account_id = user.account.id.into_uuid();
+                    let previous_spotify_id = user.account.spotify.user_id.to_string();
+                    let model = spotify_auth.into_model();
+                    let spotify_id = model.user_id.clone();
+
+                    let owner = InternalServerError::wrap(
+                        Account::find()
+                            .filter(account::Column::Spotify.eq(spotify_id.clone()))
+                            .lock_exclusive()
+                            .one(transaction),
+                        error_span!("finding account of spotify user", spotify_id),
+                    )
+                    .await?;
+
+                    if owner.is_some_and(|owner| owner.id != account_id) {
+                        return Ok(Err(SpotifyAccountAlreadyTakenError));
+                    }
+
+                    InternalServerError::wrap(
+                        SpotifyAuth::insert(model.into_active_model())
+                            .on_conflict(
+                                OnConflict::column(spotify_auth::Column::UserId)
+                                    // Do not update created_at
+                                    .update_columns([
+                                        spotify_auth::Column::AccessToken,
+                                        spotify_auth::Column::ExpiresAt,
+                                        spotify_auth::Column::RefreshToken,
+                                        spotify_auth::Column::TokenKeyId,
+                                        spotify_auth::Column::TokenDataKey,
+                                    ])
+                                    .to_owned(),
+                            )
+                            .exec(transaction),
+                        error_span!(
+                            "updating saved spotify authentication details",
+                            spotify = spotify_id
+                        ),
+                    )
+                    .await?;
+
+                    if spotify_id == previous_spotify_id {
+                        return Ok(Ok(()));
+                    }
+
+                    InternalServerError::wrap(
+                        Account::update_many()
+                            .filter(account::Column::Id.eq(account_id))
+                            .col_expr(account::Column::Spotify, Expr::value(spotify_id.clone()))
+                            .exec(transaction),
+                        error_span!("linking spotify user to account", spotify_id),
+                    )
+                    .await?;
+
+                    // Only after the account stopped referencing it, as deleting cascades
+                    InternalServerError::wrap(
+                        SpotifyAuth::delete_by_id(previous_spotify_id.clone()).exec(transaction),
+                        error_span!(
+                            "removing previous spotify authentication details",
+                            spotify = previous_spotify_id
+                        ),
+                    )
+                    .await?;
+
+                    Ok(Ok(()))
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })
+    }
+
+    /// Link a github account to the account of the user, unless it is
+    /// already linked to a different account, in which case nothing is changed
     #[tracing::instrument(skip_all, fields(github = github_auth.user_id.0, user = ?user.account.id))]
     pub async fn associate_github_to_account(
         &self,
@@ -351,24 +663,23 @@ impl Database {
                     let github_auth = github_auth.into_model(user.account.id);
                     let github_id = github_auth.user_id.clone();
 
-                    // Update the saved github authentication details for this user
-                    let github_auth = InternalServerError::wrap(
-                        GithubAuth::insert(github_auth.into_active_model())
-                            .on_conflict(
-                                OnConflict::column(github_auth::Column::UserId)
-                                    // Do not update created_at
-                                    .update_columns([github_auth::Column::AccessToken])
-                                    .to_owned(),
-                            )
-                            .exec_with_returning(transaction),
-                        error_span!("updating saved github authentication details", ?github_id),
+                    // Locked so that a concurrent link can not claim it in between
+                    let existing = InternalServerError::wrap(
+                        GithubAuth::find_by_id(github_id.clone())
+                            .lock_exclusive()
+                            .one(transaction),
+                        error_span!("finding saved github authentication details", ?github_id),
                     )
                     .await?;
 
-                    if github_auth.account != user.account.id.into_uuid() {
-                        return Ok(Err(GithubAccountAlreadyTakenError));
+                    if let Some(existing) = existing {
+                        if existing.account != github_auth.account {
+                            return Ok(Err(GithubAccountAlreadyTakenError));
+                        }
                     }
 
+                    save_github_auth(transaction, github_auth).await?;
+
                     Ok(Ok(()))
                 })
             })
@@ -379,9 +690,121 @@ impl Database {
             })
     }
 
+    /// Move a github account that is linked to another account over to the
+    /// account of the user, after the user has authorized it again
+    #[tracing::instrument(skip_all, fields(github = github_auth.user_id.0, user = ?user.account.id))]
+    pub async fn transfer_github_to_account(
+        &self,
+        user: User,
+        github_auth: GithubAuthentication,
+    ) -> Result<(), InternalServerError> {
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    let github_auth = github_auth.into_model(user.account.id);
+                    let github_id = github_auth.user_id.clone();
+
+                    let DeleteResult { rows_affected } = InternalServerError::wrap(
+                        GithubAuth::delete_by_id(github_id.clone()).exec(transaction),
+                        error_span!("unlinking github account from previous account", ?github_id),
+                    )
+                    .await?;
+
+                    if rows_affected == 0 {
+                        tracing::warn!("github account was not linked to any account anymore");
+                    }
+
+                    save_github_auth(transaction, github_auth).await
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })
+    }
+
+    /// Refresh the github tokens of the account if they are about to expire,
+    /// the row is locked so that concurrent requests do not each use the same
+    /// single use refresh token
+    #[tracing::instrument(skip(self, reqwest))]
+    pub async fn refresh_github_authentication(
+        &self,
+        account: AccountId,
+        reqwest: &reqwest::Client,
+    ) -> Result<Option<GithubAuthentication>, InternalServerError> {
+        let reqwest = reqwest.clone();
+
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    let github_auth = InternalServerError::wrap(
+                        GithubAuth::find()
+                            .filter(github_auth::Column::Account.eq(account.into_uuid()))
+                            .lock_exclusive()
+                            .one(transaction),
+                        error_span!("finding saved github authentication details"),
+                    )
+                    .await?;
+
+                    let Some(github_auth) = github_auth else {
+                        return Ok(None);
+                    };
+
+                    let github_auth = GithubAuthentication::from_model(github_auth)?;
+                    if !github_auth.needs_refresh() {
+                        // Already refreshed while waiting for the lock
+                        return Ok(Some(github_auth));
+                    }
+
+                    let refreshed = github_auth.refresh(&reqwest).await?;
+                    let model = refreshed.into_model(account);
+
+                    InternalServerError::wrap(
+                        GithubAuth::update(model.clone().into_active_model().reset_all())
+                            .exec(transaction),
+                        error_span!("saving refreshed github authentication details"),
+                    )
+                    .await?;
+
+                    GithubAuthentication::from_model(model).map(Some)
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })
+    }
+
+    /// Choose the repository that backups of the account are committed to
+    #[tracing::instrument(skip(self))]
+    pub async fn set_github_installation(
+        &self,
+        account: AccountId,
+        installation: GithubInstallation,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            GithubAuth::update_many()
+                .filter(github_auth::Column::Account.eq(account.into_uuid()))
+                .col_expr(
+                    github_auth::Column::InstallationId,
+                    Expr::value(installation.id.0 as i64),
+                )
+                .col_expr(
+                    github_auth::Column::RepositoryId,
+                    Expr::value(installation.repository.0 as i64),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip_all, fields(user = ?user.account.id))]
-    pub async fn remove_github_from_account(&self, user: User) -> Result<(), InternalServerError> {
-        if let Some(github) = user.account.github {
+    pub async fn remove_github_from_account(&self, user: &User) -> Result<(), InternalServerError> {
+        if let Some(github) = &user.account.github {
             InternalServerError::wrap_in_current_span(
                 GithubAuth::delete_by_id(github.user_id.to_string()).exec(&self.connection),
             )
@@ -393,3 +816,5 @@ impl Database {
 }
 
 pub struct GithubAccountAlreadyTakenError;
+
+pub struct SpotifyAccountAlreadyTakenError;
@@ -0,0 +1,138 @@
+use entity::{account, prelude::*, user_session};
+use rspotify::prelude::Id;
+use sea_orm::{
+    prelude::*, sea_query::Expr, DeleteResult, QuerySelect, TransactionError, TransactionTrait,
+};
+use time::OffsetDateTime;
+use tracing::error_span;
+
+use crate::{pages::InternalServerError, router::authentication};
+
+use super::{id::AccountId, Database, PrefixExt};
+
+impl Database {
+    /// Mark the account for deletion at `at` and log out all of its sessions,
+    /// logging in again before then allows cancelling
+    #[tracing::instrument(skip(self))]
+    pub async fn schedule_account_deletion(
+        &self,
+        account: AccountId,
+        at: OffsetDateTime,
+    ) -> Result<crate::router::session::UserSession, InternalServerError> {
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    InternalServerError::wrap(
+                        Account::update_many()
+                            .filter(account::Column::Id.eq(account.into_uuid()))
+                            .col_expr(account::Column::DeletionScheduledAt, Expr::value(at))
+                            .exec(transaction),
+                        error_span!("scheduling account deletion"),
+                    )
+                    .await?;
+
+                    InternalServerError::wrap(
+                        UserSession::delete_many()
+                            .filter(user_session::Column::Account.eq(account.into_uuid()))
+                            .exec(transaction),
+                        error_span!("logging out all sessions"),
+                    )
+                    .await?;
+
+                    Ok(())
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })?;
+
+        Ok(crate::router::session::UserSession::remove())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn cancel_account_deletion(
+        &self,
+        account: AccountId,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::update_many()
+                .filter(account::Column::Id.eq(account.into_uuid()))
+                .col_expr(
+                    account::Column::DeletionScheduledAt,
+                    Expr::value(Option::<OffsetDateTime>::None),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every account whose deletion was scheduled before `now`
+    #[tracing::instrument(skip(self))]
+    pub async fn accounts_due_for_deletion(
+        &self,
+        now: OffsetDateTime,
+    ) -> Result<Vec<authentication::Account>, InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::find()
+                .select_only()
+                .add_columns(Account)
+                .left_join(SpotifyAuth)
+                .add_columns(SpotifyAuth)
+                .left_join(GithubAuth)
+                .add_columns(GithubAuth)
+                .filter(account::Column::DeletionScheduledAt.lte(now))
+                .into_model::<authentication::Account>()
+                .all(&self.connection),
+        )
+        .await
+    }
+
+    /// Delete an account that is due for deletion, returns false if the
+    /// deletion was cancelled in the meantime
+    #[tracing::instrument(skip_all, fields(account = %account.id))]
+    pub async fn delete_scheduled_account(
+        &self,
+        account: &authentication::Account,
+        now: OffsetDateTime,
+    ) -> Result<bool, InternalServerError> {
+        let id = account.id;
+        let spotify_id = account.spotify.user_id.id().to_string();
+
+        self.connection
+            .transaction(|transaction| {
+                Box::pin(async move {
+                    // Locked so that cancelling at the same time either wins or waits
+                    let due = InternalServerError::wrap(
+                        Account::find_by_id(id.into_uuid())
+                            .filter(account::Column::DeletionScheduledAt.lte(now))
+                            .lock_exclusive()
+                            .one(transaction),
+                        error_span!("checking account is still due for deletion"),
+                    )
+                    .await?;
+
+                    if due.is_none() {
+                        return Ok(false);
+                    }
+
+                    // Deleting the spotify authentication will cascade to the user account
+                    let DeleteResult { rows_affected } = InternalServerError::wrap(
+                        SpotifyAuth::delete_by_id(spotify_id).exec(transaction),
+                        error_span!("deleting account"),
+                    )
+                    .await?;
+
+                    Ok(rows_affected > 0)
+                })
+            })
+            .await
+            .map_err(|error| match error {
+                TransactionError::Connection(error) => InternalServerError::from_error(error),
+                TransactionError::Transaction(error) => error,
+            })
+    }
+}
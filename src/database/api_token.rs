@@ -0,0 +1,104 @@
+use entity::{api_token, prelude::*};
+use sea_orm::{prelude::*, sea_query::Expr, Condition, IntoActiveModel, QueryOrder};
+use time::OffsetDateTime;
+
+use crate::{api_tokens, pages::InternalServerError};
+
+use super::{
+    id::{AccountId, ApiTokenId},
+    Database,
+};
+
+impl Database {
+    #[tracing::instrument(skip(self))]
+    pub async fn list_api_tokens(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<api_tokens::ApiToken>, InternalServerError> {
+        let tokens = InternalServerError::wrap_in_current_span(
+            ApiToken::find()
+                .filter(api_token::Column::Account.eq(account.into_uuid()))
+                .order_by_asc(api_token::Column::CreatedAt)
+                .all(&self.connection),
+        )
+        .await?;
+
+        Ok(tokens
+            .into_iter()
+            .map(api_tokens::ApiToken::from_model)
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(account = %token.account, token = %token.id))]
+    pub async fn create_api_token(
+        &self,
+        token: api_tokens::ApiToken,
+        token_hash: String,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            ApiToken::insert(token.into_model(token_hash).into_active_model())
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the account had a token with the id
+    #[tracing::instrument(skip(self))]
+    pub async fn delete_api_token(
+        &self,
+        account: AccountId,
+        token: ApiTokenId,
+    ) -> Result<bool, InternalServerError> {
+        let result = InternalServerError::wrap_in_current_span(
+            ApiToken::delete_many()
+                .filter(api_token::Column::Id.eq(token.into_uuid()))
+                .filter(api_token::Column::Account.eq(account.into_uuid()))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub async fn find_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<api_tokens::ApiToken>, InternalServerError> {
+        let token = InternalServerError::wrap_in_current_span(
+            ApiToken::find()
+                .filter(api_token::Column::TokenHash.eq(token_hash))
+                .one(&self.connection),
+        )
+        .await?;
+
+        Ok(token.map(api_tokens::ApiToken::from_model))
+    }
+
+    /// Record that the token was used, unless that was already recorded
+    /// after `unless_since`
+    #[tracing::instrument(skip(self))]
+    pub async fn touch_api_token(
+        &self,
+        token: ApiTokenId,
+        now: OffsetDateTime,
+        unless_since: OffsetDateTime,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            ApiToken::update_many()
+                .filter(api_token::Column::Id.eq(token.into_uuid()))
+                .filter(
+                    Condition::any()
+                        .add(api_token::Column::LastUsedAt.is_null())
+                        .add(api_token::Column::LastUsedAt.lt(unless_since)),
+                )
+                .col_expr(api_token::Column::LastUsedAt, Expr::value(now))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
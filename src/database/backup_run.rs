@@ -0,0 +1,90 @@
+use entity::{backup_run, prelude::*};
+use sea_orm::{prelude::*, IntoActiveModel, QueryOrder, QuerySelect};
+use time::OffsetDateTime;
+
+use crate::{backup, pages::InternalServerError};
+
+use super::{id::AccountId, Database};
+
+impl Database {
+    #[tracing::instrument(skip_all, fields(account = %run.account, run = %run.id))]
+    pub async fn record_backup_run(
+        &self,
+        run: backup::BackupRun,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            BackupRun::insert(run.into_model().into_active_model()).exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent backup runs of an account, newest first
+    #[tracing::instrument(skip(self))]
+    pub async fn list_backup_runs(
+        &self,
+        account: AccountId,
+        limit: u64,
+    ) -> Result<Vec<backup::BackupRun>, InternalServerError> {
+        let runs = InternalServerError::wrap_in_current_span(
+            BackupRun::find()
+                .filter(backup_run::Column::Account.eq(account.into_uuid()))
+                .order_by_desc(backup_run::Column::StartedAt)
+                .limit(limit)
+                .all(&self.connection),
+        )
+        .await?;
+
+        Ok(runs
+            .into_iter()
+            .map(backup::BackupRun::from_model)
+            .collect())
+    }
+
+    /// Backup runs of an account started after `since`, oldest first
+    #[tracing::instrument(skip(self))]
+    pub async fn list_backup_runs_since(
+        &self,
+        account: AccountId,
+        since: OffsetDateTime,
+    ) -> Result<Vec<backup::BackupRun>, InternalServerError> {
+        let runs = InternalServerError::wrap_in_current_span(
+            BackupRun::find()
+                .filter(backup_run::Column::Account.eq(account.into_uuid()))
+                .filter(backup_run::Column::StartedAt.gt(since))
+                .order_by_asc(backup_run::Column::StartedAt)
+                .all(&self.connection),
+        )
+        .await?;
+
+        Ok(runs
+            .into_iter()
+            .map(backup::BackupRun::from_model)
+            .collect())
+    }
+
+    /// Most recent backup runs of an account that committed a change, newest
+    /// first
+    #[tracing::instrument(skip(self))]
+    pub async fn list_changed_backup_runs(
+        &self,
+        account: AccountId,
+        limit: u64,
+    ) -> Result<Vec<backup::BackupRun>, InternalServerError> {
+        let runs = InternalServerError::wrap_in_current_span(
+            BackupRun::find()
+                .filter(backup_run::Column::Account.eq(account.into_uuid()))
+                .filter(backup_run::Column::Status.eq("committed"))
+                .order_by_desc(backup_run::Column::StartedAt)
+                .limit(limit)
+                .all(&self.connection),
+        )
+        .await?;
+
+        Ok(runs
+            .into_iter()
+            .map(backup::BackupRun::from_model)
+            .collect())
+    }
+}
@@ -0,0 +1,107 @@
+use entity::{
+    account, api_token, backup_run, github_auth, notification_settings, prelude::*, spotify_auth,
+    user_session, webhook, webhook_delivery,
+};
+use sea_orm::{prelude::*, QueryOrder};
+use tokio::try_join;
+
+use crate::pages::InternalServerError;
+
+use super::{id::AccountId, Database};
+
+/// Every row stored about an account, secrets included, the export picks
+/// what is handed out
+#[derive(Debug)]
+pub struct AccountRows {
+    pub account: account::Model,
+    pub spotify_auth: Option<spotify_auth::Model>,
+    pub github_auth: Option<github_auth::Model>,
+    pub sessions: Vec<user_session::Model>,
+    pub notification_settings: Option<notification_settings::Model>,
+    pub backup_runs: Vec<backup_run::Model>,
+    pub webhooks: Vec<webhook::Model>,
+    pub webhook_deliveries: Vec<webhook_delivery::Model>,
+    pub api_tokens: Vec<api_token::Model>,
+}
+
+impl Database {
+    #[tracing::instrument(skip(self))]
+    pub async fn account_rows(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<AccountRows>, InternalServerError> {
+        let Some(account) = InternalServerError::wrap_in_current_span(
+            Account::find_by_id(account.into_uuid()).one(&self.connection),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let (
+            spotify_auth,
+            github_auth,
+            sessions,
+            notification_settings,
+            backup_runs,
+            webhooks,
+            webhook_deliveries,
+            api_tokens,
+        ) = try_join!(
+            InternalServerError::wrap_in_current_span(
+                account.find_related(SpotifyAuth).one(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account.find_related(GithubAuth).one(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account
+                    .find_related(UserSession)
+                    .order_by_asc(user_session::Column::CreatedAt)
+                    .all(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account
+                    .find_related(NotificationSettings)
+                    .one(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account
+                    .find_related(BackupRun)
+                    .order_by_asc(backup_run::Column::StartedAt)
+                    .all(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account
+                    .find_related(Webhook)
+                    .order_by_asc(webhook::Column::CreatedAt)
+                    .all(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                WebhookDelivery::find()
+                    .inner_join(Webhook)
+                    .filter(webhook::Column::Account.eq(account.id))
+                    .order_by_asc(webhook_delivery::Column::AttemptedAt)
+                    .all(&self.connection)
+            ),
+            InternalServerError::wrap_in_current_span(
+                account
+                    .find_related(ApiToken)
+                    .order_by_asc(api_token::Column::CreatedAt)
+                    .all(&self.connection)
+            ),
+        )?;
+
+        Ok(Some(AccountRows {
+            account,
+            spotify_auth,
+            github_auth,
+            sessions,
+            notification_settings,
+            backup_runs,
+            webhooks,
+            webhook_deliveries,
+            api_tokens,
+        }))
+    }
+}
@@ -0,0 +1,70 @@
+use entity::{account, prelude::*};
+use sea_orm::{prelude::*, sea_query::Expr, QuerySelect};
+
+use crate::pages::InternalServerError;
+
+use super::{id::AccountId, Database};
+
+impl Database {
+    #[tracing::instrument(skip(self))]
+    pub async fn feed_token(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<Uuid>, InternalServerError> {
+        let token = InternalServerError::wrap_in_current_span(
+            Account::find_by_id(account.into_uuid())
+                .select_only()
+                .column(account::Column::FeedToken)
+                .into_tuple::<Option<Uuid>>()
+                .one(&self.connection),
+        )
+        .await?;
+
+        Ok(token.flatten())
+    }
+
+    /// The token is the only credential needed to read the feed, so it is
+    /// not recorded here, request spans and sentry events redact it from the
+    /// url
+    #[tracing::instrument(skip_all)]
+    pub async fn find_account_by_feed_token(
+        &self,
+        token: Uuid,
+    ) -> Result<Option<AccountId>, InternalServerError> {
+        let account = InternalServerError::wrap_in_current_span(
+            Account::find()
+                .filter(account::Column::FeedToken.eq(token))
+                .one(&self.connection),
+        )
+        .await?;
+
+        Ok(account.map(AccountId::from_model))
+    }
+
+    /// Replace the feed token of an account, invalidating the previous feed url
+    #[tracing::instrument(skip(self))]
+    pub async fn rotate_feed_token(&self, account: AccountId) -> Result<(), InternalServerError> {
+        self.set_feed_token(account, Some(Uuid::new_v4())).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn disable_feed(&self, account: AccountId) -> Result<(), InternalServerError> {
+        self.set_feed_token(account, None).await
+    }
+
+    async fn set_feed_token(
+        &self,
+        account: AccountId,
+        token: Option<Uuid>,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::update_many()
+                .filter(account::Column::Id.eq(account.into_uuid()))
+                .col_expr(account::Column::FeedToken, Expr::value(token))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
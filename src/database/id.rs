@@ -2,6 +2,7 @@ use std::fmt::Display;
 
 use rspotify::prelude::Id;
 use sea_orm::prelude::Uuid;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GithubUserId(u64);
@@ -49,7 +50,7 @@ impl Display for SpotifyUserId {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AccountId(Uuid);
 
 impl AccountId {
@@ -61,6 +62,22 @@ impl AccountId {
         Self(session.account)
     }
 
+    pub fn from_backup_run(run: &entity::backup_run::Model) -> Self {
+        Self(run.account)
+    }
+
+    pub fn from_notification_settings(settings: &entity::notification_settings::Model) -> Self {
+        Self(settings.account)
+    }
+
+    pub fn from_webhook(webhook: &entity::webhook::Model) -> Self {
+        Self(webhook.account)
+    }
+
+    pub fn from_api_token(token: &entity::api_token::Model) -> Self {
+        Self(token.account)
+    }
+
     pub fn into_uuid(self) -> Uuid {
         self.0
     }
@@ -88,6 +105,12 @@ impl UserSessionId {
     pub const fn from_raw(uuid: Uuid) -> Self {
         Self(uuid)
     }
+
+    /// The id is the secret in the session cookie, the handle identifies a
+    /// session on pages and in forms without revealing it
+    pub fn public_handle(&self) -> String {
+        hex::encode(&Sha256::digest(self.0.as_bytes())[..8])
+    }
 }
 
 impl Display for UserSessionId {
@@ -95,3 +118,113 @@ impl Display for UserSessionId {
         self.0.fmt(f)
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupRunId(Uuid);
+
+impl BackupRunId {
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    pub fn from_model(run: &entity::backup_run::Model) -> Self {
+        Self(run.id)
+    }
+
+    pub fn from_webhook_delivery(delivery: &entity::webhook_delivery::Model) -> Self {
+        Self(delivery.backup_run)
+    }
+
+    pub fn into_uuid(self) -> Uuid {
+        self.0
+    }
+}
+
+impl Display for BackupRunId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebhookId(Uuid);
+
+impl WebhookId {
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    pub fn from_model(webhook: &entity::webhook::Model) -> Self {
+        Self(webhook.id)
+    }
+
+    pub fn from_delivery(delivery: &entity::webhook_delivery::Model) -> Self {
+        Self(delivery.webhook)
+    }
+
+    // TODO: do away with
+    pub const fn from_raw(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+
+    pub fn into_uuid(self) -> Uuid {
+        self.0
+    }
+}
+
+impl Display for WebhookId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebhookDeliveryId(Uuid);
+
+impl WebhookDeliveryId {
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    pub fn from_model(delivery: &entity::webhook_delivery::Model) -> Self {
+        Self(delivery.id)
+    }
+
+    pub fn into_uuid(self) -> Uuid {
+        self.0
+    }
+}
+
+impl Display for WebhookDeliveryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiTokenId(Uuid);
+
+impl ApiTokenId {
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    pub fn from_model(token: &entity::api_token::Model) -> Self {
+        Self(token.id)
+    }
+
+    // TODO: do away with
+    pub const fn from_raw(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+
+    pub fn into_uuid(self) -> Uuid {
+        self.0
+    }
+}
+
+impl Display for ApiTokenId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
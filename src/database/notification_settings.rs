@@ -0,0 +1,197 @@
+use entity::{notification_settings, prelude::*};
+use migration::OnConflict;
+use sea_orm::{prelude::*, sea_query::Expr, Condition, IntoActiveModel};
+use time::OffsetDateTime;
+
+use crate::{
+    notifications::{self, NotificationKind},
+    pages::InternalServerError,
+};
+
+use super::{id::AccountId, Database};
+
+impl Database {
+    #[tracing::instrument(skip(self))]
+    pub async fn get_notification_settings(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<notifications::NotificationSettings>, InternalServerError> {
+        let settings = InternalServerError::wrap_in_current_span(
+            NotificationSettings::find_by_id(account.into_uuid()).one(&self.connection),
+        )
+        .await?;
+
+        Ok(settings.map(notifications::NotificationSettings::from_model))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn find_notification_settings_by_token(
+        &self,
+        unsubscribe_token: Uuid,
+    ) -> Result<Option<notifications::NotificationSettings>, InternalServerError> {
+        let settings = InternalServerError::wrap_in_current_span(
+            NotificationSettings::find()
+                .filter(notification_settings::Column::UnsubscribeToken.eq(unsubscribe_token))
+                .one(&self.connection),
+        )
+        .await?;
+
+        Ok(settings.map(notifications::NotificationSettings::from_model))
+    }
+
+    #[tracing::instrument(skip_all, fields(account = %settings.account))]
+    pub async fn save_notification_settings(
+        &self,
+        settings: notifications::NotificationSettings,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            NotificationSettings::insert(settings.into_model().into_active_model())
+                .on_conflict(
+                    OnConflict::column(notification_settings::Column::Account)
+                        // Do not update the token, created_at or the summary schedule
+                        .update_columns([
+                            notification_settings::Column::Email,
+                            notification_settings::Column::BackupFailures,
+                            notification_settings::Column::AuthorizationRevoked,
+                            notification_settings::Column::WeeklySummary,
+                            notification_settings::Column::UnavailableTracks,
+                            notification_settings::Column::EmailConfirmedAt,
+                            notification_settings::Column::ConfirmationToken,
+                            notification_settings::Column::ConfirmationSentAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn delete_notification_settings(
+        &self,
+        account: AccountId,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            NotificationSettings::delete_by_id(account.into_uuid()).exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark the address as confirmed if the token is valid and was sent after
+    /// `sent_after`, returning whether it was
+    #[tracing::instrument(skip(self))]
+    pub async fn confirm_notification_email(
+        &self,
+        confirmation_token: Uuid,
+        sent_after: OffsetDateTime,
+    ) -> Result<bool, InternalServerError> {
+        let result = InternalServerError::wrap_in_current_span(
+            NotificationSettings::update_many()
+                .filter(notification_settings::Column::ConfirmationToken.eq(confirmation_token))
+                .filter(notification_settings::Column::ConfirmationSentAt.gt(sent_after))
+                .col_expr(
+                    notification_settings::Column::EmailConfirmedAt,
+                    Expr::value(OffsetDateTime::now_utc()),
+                )
+                .col_expr(
+                    notification_settings::Column::ConfirmationToken,
+                    Expr::value(Option::<Uuid>::None),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Turn off one kind of notification, or all of them, returning whether
+    /// the token matched any settings
+    #[tracing::instrument(skip(self))]
+    pub async fn unsubscribe(
+        &self,
+        unsubscribe_token: Uuid,
+        kind: Option<NotificationKind>,
+    ) -> Result<bool, InternalServerError> {
+        let columns = match kind {
+            Some(NotificationKind::BackupFailures) => {
+                &[notification_settings::Column::BackupFailures][..]
+            }
+            Some(NotificationKind::AuthorizationRevoked) => {
+                &[notification_settings::Column::AuthorizationRevoked][..]
+            }
+            Some(NotificationKind::WeeklySummary) => {
+                &[notification_settings::Column::WeeklySummary][..]
+            }
+            Some(NotificationKind::UnavailableTracks) => {
+                &[notification_settings::Column::UnavailableTracks][..]
+            }
+            None => &[
+                notification_settings::Column::BackupFailures,
+                notification_settings::Column::AuthorizationRevoked,
+                notification_settings::Column::WeeklySummary,
+                notification_settings::Column::UnavailableTracks,
+            ][..],
+        };
+
+        let mut update = NotificationSettings::update_many()
+            .filter(notification_settings::Column::UnsubscribeToken.eq(unsubscribe_token));
+        for column in columns {
+            update = update.col_expr(*column, Expr::value(false));
+        }
+
+        let result =
+            InternalServerError::wrap_in_current_span(update.exec(&self.connection)).await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Settings of accounts that want a weekly summary and have not received
+    /// one since `cutoff`
+    #[tracing::instrument(skip(self))]
+    pub async fn list_due_weekly_summaries(
+        &self,
+        cutoff: OffsetDateTime,
+    ) -> Result<Vec<notifications::NotificationSettings>, InternalServerError> {
+        let settings = InternalServerError::wrap_in_current_span(
+            NotificationSettings::find()
+                .filter(notification_settings::Column::WeeklySummary.eq(true))
+                .filter(notification_settings::Column::EmailConfirmedAt.is_not_null())
+                .filter(
+                    Condition::any()
+                        .add(notification_settings::Column::LastWeeklySummary.is_null())
+                        .add(notification_settings::Column::LastWeeklySummary.lte(cutoff)),
+                )
+                .all(&self.connection),
+        )
+        .await?;
+
+        Ok(settings
+            .into_iter()
+            .map(notifications::NotificationSettings::from_model)
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn mark_weekly_summary_sent(
+        &self,
+        account: AccountId,
+        sent_at: OffsetDateTime,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            NotificationSettings::update_many()
+                .filter(notification_settings::Column::Account.eq(account.into_uuid()))
+                .col_expr(
+                    notification_settings::Column::LastWeeklySummary,
+                    Expr::value(sent_at),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use entity::{prelude::*, user_session};
+use sea_orm::{prelude::*, sea_query::Expr, Condition, DeleteResult, QueryOrder};
+use time::OffsetDateTime;
+
+use crate::pages::InternalServerError;
+
+use super::{
+    id::{AccountId, UserSessionId},
+    Database,
+};
+
+impl Database {
+    /// Every session the account is logged in with, most recently used first
+    #[tracing::instrument(skip(self))]
+    pub async fn list_user_sessions(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<user_session::Model>, InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            UserSession::find()
+                .filter(user_session::Column::Account.eq(account.into_uuid()))
+                .order_by_desc(user_session::Column::LastSeen)
+                .all(&self.connection),
+        )
+        .await
+    }
+
+    /// Log out the session of the account with the given public handle,
+    /// returns whether it existed
+    #[tracing::instrument(skip(self, handle))]
+    pub async fn revoke_user_session(
+        &self,
+        account: AccountId,
+        handle: &str,
+    ) -> Result<bool, InternalServerError> {
+        let session = self
+            .list_user_sessions(account)
+            .await?
+            .into_iter()
+            .map(UserSessionId::from_model)
+            .find(|session| session.public_handle() == handle);
+
+        let Some(session) = session else {
+            return Ok(false);
+        };
+
+        let DeleteResult { rows_affected } = InternalServerError::wrap_in_current_span(
+            UserSession::delete_many()
+                .filter(user_session::Column::Id.eq(session.into_uuid()))
+                .filter(user_session::Column::Account.eq(account.into_uuid()))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Log out every session of the account except `keep`, returns the number
+    /// of sessions that were logged out
+    #[tracing::instrument(skip(self, keep))]
+    pub async fn revoke_other_user_sessions(
+        &self,
+        account: AccountId,
+        keep: UserSessionId,
+    ) -> Result<u64, InternalServerError> {
+        let DeleteResult { rows_affected } = InternalServerError::wrap_in_current_span(
+            UserSession::delete_many()
+                .filter(user_session::Column::Account.eq(account.into_uuid()))
+                .filter(user_session::Column::Id.ne(keep.into_uuid()))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(rows_affected)
+    }
+
+    #[tracing::instrument(skip(self, session))]
+    pub async fn touch_user_session(
+        &self,
+        session: UserSessionId,
+        last_seen: OffsetDateTime,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            UserSession::update_many()
+                .filter(user_session::Column::Id.eq(session.into_uuid()))
+                .col_expr(user_session::Column::LastSeen, Expr::value(last_seen))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete every session that was idle since `idle_cutoff` or created
+    /// before `absolute_cutoff`, returns the number of deleted sessions
+    #[tracing::instrument(skip(self))]
+    pub async fn prune_user_sessions(
+        &self,
+        idle_cutoff: OffsetDateTime,
+        absolute_cutoff: OffsetDateTime,
+    ) -> Result<u64, InternalServerError> {
+        let DeleteResult { rows_affected } = InternalServerError::wrap_in_current_span(
+            UserSession::delete_many()
+                .filter(
+                    Condition::any()
+                        .add(user_session::Column::LastSeen.lt(idle_cutoff))
+                        .add(user_session::Column::CreatedAt.lt(absolute_cutoff)),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(rows_affected)
+    }
+}
@@ -0,0 +1,118 @@
+use entity::{github_auth, prelude::*, spotify_auth};
+use sea_orm::{prelude::*, sea_query::Expr, QuerySelect};
+use tracing::error_span;
+
+use crate::{environment::TOKEN_KEYRING, internal_server_error, pages::InternalServerError};
+
+use super::Database;
+
+impl Database {
+    /// Wrap the data keys of every row that still uses an older master key
+    /// with the current one, so that older keys can be removed from the
+    /// keyring.
+    ///
+    /// Rows whose tokens were replaced since they were read, by a login or a
+    /// refresh, already use a new data key and are skipped instead of
+    /// overwritten
+    #[tracing::instrument(skip(self), fields(key_id = TOKEN_KEYRING.current_key_id()))]
+    pub async fn reencrypt_tokens(&self) -> Result<ReencryptedTokens, InternalServerError> {
+        let current = TOKEN_KEYRING.current_key_id();
+        let mut reencrypted = ReencryptedTokens::default();
+
+        let spotify = InternalServerError::wrap(
+            SpotifyAuth::find()
+                .select_only()
+                .columns([
+                    spotify_auth::Column::UserId,
+                    spotify_auth::Column::TokenKeyId,
+                    spotify_auth::Column::TokenDataKey,
+                ])
+                .filter(spotify_auth::Column::TokenKeyId.ne(current))
+                .into_tuple::<(String, String, String)>()
+                .all(&self.connection),
+            error_span!("finding spotify tokens with older keys"),
+        )
+        .await?;
+
+        for (user_id, key_id, data_key) in spotify {
+            let rewrapped = rewrap(&key_id, &data_key)?;
+
+            let result = InternalServerError::wrap(
+                SpotifyAuth::update_many()
+                    .filter(spotify_auth::Column::UserId.eq(user_id.clone()))
+                    .filter(spotify_auth::Column::TokenKeyId.eq(key_id))
+                    .filter(spotify_auth::Column::TokenDataKey.eq(data_key))
+                    .col_expr(spotify_auth::Column::TokenKeyId, Expr::value(current))
+                    .col_expr(spotify_auth::Column::TokenDataKey, Expr::value(rewrapped))
+                    .exec(&self.connection),
+                error_span!("rewrapping spotify data key", spotify = user_id),
+            )
+            .await?;
+
+            reencrypted.count(result.rows_affected);
+        }
+
+        let github = InternalServerError::wrap(
+            GithubAuth::find()
+                .select_only()
+                .columns([
+                    github_auth::Column::UserId,
+                    github_auth::Column::TokenKeyId,
+                    github_auth::Column::TokenDataKey,
+                ])
+                .filter(github_auth::Column::TokenKeyId.ne(current))
+                .into_tuple::<(String, String, String)>()
+                .all(&self.connection),
+            error_span!("finding github tokens with older keys"),
+        )
+        .await?;
+
+        for (user_id, key_id, data_key) in github {
+            let rewrapped = rewrap(&key_id, &data_key)?;
+
+            let result = InternalServerError::wrap(
+                GithubAuth::update_many()
+                    .filter(github_auth::Column::UserId.eq(user_id.clone()))
+                    .filter(github_auth::Column::TokenKeyId.eq(key_id))
+                    .filter(github_auth::Column::TokenDataKey.eq(data_key))
+                    .col_expr(github_auth::Column::TokenKeyId, Expr::value(current))
+                    .col_expr(github_auth::Column::TokenDataKey, Expr::value(rewrapped))
+                    .exec(&self.connection),
+                error_span!("rewrapping github data key", github = user_id),
+            )
+            .await?;
+
+            reencrypted.count(result.rows_affected);
+        }
+
+        Ok(reencrypted)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReencryptedTokens {
+    pub updated: u64,
+    /// Rows that changed between reading and updating them
+    pub skipped: u64,
+}
+
+impl ReencryptedTokens {
+    fn count(&mut self, rows_affected: u64) {
+        if rows_affected == 0 {
+            self.skipped += 1;
+        } else {
+            self.updated += rows_affected;
+        }
+    }
+}
+
+fn rewrap(key_id: &str, data_key: &str) -> Result<String, InternalServerError> {
+    match TOKEN_KEYRING.rewrap(key_id, data_key) {
+        Ok(Some(data_key)) => Ok(data_key),
+        Ok(None) => Err(internal_server_error!(
+            "data key is already wrapped by the current key",
+            key_id
+        )),
+        Err(error) => Err(InternalServerError::from_error(error)),
+    }
+}
@@ -0,0 +1,159 @@
+use entity::{account, prelude::*, webhook, webhook_delivery};
+use sea_orm::{prelude::*, sea_query::Expr, IntoActiveModel, QueryOrder, QuerySelect};
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{
+    internal_server_error,
+    pages::InternalServerError,
+    webhooks::{self, generate_secret},
+};
+
+use super::{
+    id::{AccountId, WebhookId},
+    Database,
+};
+
+impl Database {
+    #[tracing::instrument(skip(self))]
+    pub async fn list_webhooks(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<webhooks::Webhook>, InternalServerError> {
+        let webhooks = InternalServerError::wrap_in_current_span(
+            Webhook::find()
+                .filter(webhook::Column::Account.eq(account.into_uuid()))
+                .order_by_asc(webhook::Column::CreatedAt)
+                .all(&self.connection),
+        )
+        .await?;
+
+        webhooks
+            .into_iter()
+            .map(webhooks::Webhook::from_model)
+            .collect()
+    }
+
+    /// Register a webhook, creating the signing secret of the account if it
+    /// does not have one yet
+    #[tracing::instrument(skip_all, fields(account = %webhook.account, webhook = %webhook.id))]
+    pub async fn create_webhook(
+        &self,
+        webhook: webhooks::Webhook,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::update_many()
+                .filter(account::Column::Id.eq(webhook.account.into_uuid()))
+                .filter(account::Column::WebhookSecret.is_null())
+                .col_expr(
+                    account::Column::WebhookSecret,
+                    Expr::value(generate_secret().expose_secret().clone()),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        InternalServerError::wrap_in_current_span(
+            Webhook::insert(webhook.into_model().into_active_model()).exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the account had a webhook with the id
+    #[tracing::instrument(skip(self))]
+    pub async fn delete_webhook(
+        &self,
+        account: AccountId,
+        webhook: WebhookId,
+    ) -> Result<bool, InternalServerError> {
+        let result = InternalServerError::wrap_in_current_span(
+            Webhook::delete_many()
+                .filter(webhook::Column::Id.eq(webhook.into_uuid()))
+                .filter(webhook::Column::Account.eq(account.into_uuid()))
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(result.rows_affected > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn webhook_secret(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<SecretString>, InternalServerError> {
+        let account = InternalServerError::wrap_in_current_span(
+            Account::find_by_id(account.into_uuid()).one(&self.connection),
+        )
+        .await?
+        .ok_or_else(|| internal_server_error!("account should exist"))?;
+
+        Ok(account.webhook_secret.map(SecretString::new))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn rotate_webhook_secret(
+        &self,
+        account: AccountId,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            Account::update_many()
+                .filter(account::Column::Id.eq(account.into_uuid()))
+                .col_expr(
+                    account::Column::WebhookSecret,
+                    Expr::value(generate_secret().expose_secret().clone()),
+                )
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(delivery = %delivery.id, webhook = %delivery.webhook))]
+    pub async fn record_webhook_delivery(
+        &self,
+        delivery: webhooks::WebhookDelivery,
+    ) -> Result<(), InternalServerError> {
+        InternalServerError::wrap_in_current_span(
+            WebhookDelivery::insert(delivery.into_model().into_active_model())
+                .exec(&self.connection),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent delivery attempts to any webhook of the account, newest first
+    #[tracing::instrument(skip(self))]
+    pub async fn list_webhook_deliveries(
+        &self,
+        account: AccountId,
+        limit: u64,
+    ) -> Result<Vec<(webhooks::WebhookDelivery, webhooks::Webhook)>, InternalServerError> {
+        let deliveries = InternalServerError::wrap_in_current_span(
+            WebhookDelivery::find()
+                .find_also_related(Webhook)
+                .filter(webhook::Column::Account.eq(account.into_uuid()))
+                .order_by_desc(webhook_delivery::Column::AttemptedAt)
+                .limit(limit)
+                .all(&self.connection),
+        )
+        .await?;
+
+        deliveries
+            .into_iter()
+            .map(|(delivery, webhook)| {
+                let webhook = webhook.ok_or_else(|| {
+                    internal_server_error!("webhook should always exist for a delivery")
+                })?;
+
+                Ok((
+                    webhooks::WebhookDelivery::from_model(delivery),
+                    webhooks::Webhook::from_model(webhook)?,
+                ))
+            })
+            .collect()
+    }
+}
@@ -1,13 +1,24 @@
 use std::{env, net::SocketAddr, path::PathBuf};
 
 use axum::http::{uri::Authority, Uri};
+use axum_extra::extract::cookie::Key;
+use lettre::message::Mailbox;
 use octocrab::{models::AppId, Octocrab};
 use once_cell::sync::Lazy;
+use secrecy::SecretString;
+use token_encryption::{Keyring, KEYS_VARIABLE};
 
 pub struct HttpEnvironment {
     pub bind: SocketAddr,
     pub static_dir: PathBuf,
     pub domain: Authority,
+    /// Key for signing cookies
+    pub cookie_key: Key,
+    /// Bearer token required to scrape `/metrics`, which is disabled without it
+    pub metrics_token: Option<SecretString>,
+    /// Whether requests arrive through a proxy that sets the client address
+    /// headers, they can be forged by clients connecting directly otherwise
+    pub trust_proxy: bool,
 }
 
 pub static HTTP_ENVIRONMENT: Lazy<HttpEnvironment> = Lazy::new(|| HttpEnvironment {
@@ -22,6 +33,14 @@ pub static HTTP_ENVIRONMENT: Lazy<HttpEnvironment> = Lazy::new(|| HttpEnvironmen
         .expect("$DOMAIN should be set")
         .parse::<Authority>()
         .expect("$DOMAIN should be a valid URI authority"),
+    cookie_key: Key::try_from(
+        hex::decode(env::var("COOKIE_KEY").expect("$COOKIE_KEY should be set"))
+            .expect("$COOKIE_KEY should be hex encoded")
+            .as_slice(),
+    )
+    .expect("$COOKIE_KEY should be at least 64 bytes long"),
+    metrics_token: env::var("METRICS_TOKEN").ok().map(SecretString::from),
+    trust_proxy: matches!(env::var("TRUST_PROXY").as_deref(), Ok("true" | "1")),
 });
 
 #[derive(Debug, Clone)]
@@ -93,3 +112,28 @@ pub static GITHUB_ENVIRONMENT: Lazy<GithubEnvironment> = Lazy::new(|| {
             .expect("$GITHUB_REDIRECT_URI should be a valid URI"),
     }
 });
+
+pub struct EmailEnvironment {
+    /// Connection url of the SMTP relay, including credentials
+    pub smtp_url: SecretString,
+    pub from: Mailbox,
+}
+
+/// Email is turned off when neither variable is set
+pub static EMAIL_ENVIRONMENT: Lazy<Option<EmailEnvironment>> =
+    Lazy::new(|| match (env::var("SMTP_URL"), env::var("EMAIL_FROM")) {
+        (Ok(smtp_url), Ok(from)) => Some(EmailEnvironment {
+            smtp_url: smtp_url.into(),
+            from: from.parse().expect("$EMAIL_FROM should be a valid mailbox"),
+        }),
+        (Err(_), Err(_)) => None,
+        _ => panic!("$SMTP_URL and $EMAIL_FROM should either both be set or both be unset"),
+    });
+
+/// Master keys that encrypt the OAuth tokens stored in the database
+pub static TOKEN_KEYRING: Lazy<Keyring> = Lazy::new(|| {
+    Keyring::parse(
+        &env::var(KEYS_VARIABLE).unwrap_or_else(|_| panic!("${KEYS_VARIABLE} should be set")),
+    )
+    .unwrap_or_else(|error| panic!("${KEYS_VARIABLE} should be a valid keyring: {error}"))
+});
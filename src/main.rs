@@ -1,17 +1,23 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::unwrap_in_result, clippy::unwrap_used)]
 
-use std::{borrow::Cow, env};
+use std::{borrow::Cow, env, sync::Arc};
 
 use color_eyre::eyre::Context;
 use database::Database;
+use router::middleware::trace::redact_path;
+use sentry::integrations::tracing::EventFilter;
 use tracing_subscriber::{prelude::*, EnvFilter};
 
+mod api_tokens;
 mod backup;
 mod database;
 mod environment;
+mod metrics;
+mod notifications;
 mod pages;
 mod router;
+mod webhooks;
 
 fn main() -> Result<(), color_eyre::Report> {
     color_eyre::install()?;
@@ -20,7 +26,17 @@ fn main() -> Result<(), color_eyre::Report> {
         .with(tracing_error::ErrorLayer::default())
         .with(tracing_subscriber::fmt::layer().pretty())
         .with(EnvFilter::from_default_env())
-        .with(sentry::integrations::tracing::layer())
+        .with(
+            sentry::integrations::tracing::layer().event_filter(|metadata| {
+                // Internal server errors are reported with their full context
+                // when they are created, the log only adds a breadcrumb
+                if metadata.target() == pages::INTERNAL_SERVER_ERROR_TARGET {
+                    EventFilter::Breadcrumb
+                } else {
+                    sentry::integrations::tracing::default_event_filter(metadata)
+                }
+            }),
+        )
         .init();
 
     // FIXME: the errors have almost no good context, find way to report color_eyre reports
@@ -39,6 +55,21 @@ fn main() -> Result<(), color_eyre::Report> {
         auto_session_tracking: true,
         session_mode: sentry::SessionMode::Request,
         trim_backtraces: true,
+        before_send: Some(Arc::new(|mut event| {
+            if let Some(url) = event
+                .request
+                .as_mut()
+                .and_then(|request| request.url.as_mut())
+            {
+                let path = redact_path(url.path()).into_owned();
+                url.set_path(&path);
+            }
+            if let Some(transaction) = &mut event.transaction {
+                *transaction = redact_path(transaction).into_owned();
+            }
+
+            Some(event)
+        })),
         ..Default::default()
     });
 
@@ -51,9 +82,35 @@ fn main() -> Result<(), color_eyre::Report> {
                 .await
                 .wrap_err("failed to setup to database")?;
 
+            // Run after adding a new master key in front of the keyring, the
+            // older keys can be removed once this has finished
+            if env::args().nth(1).as_deref() == Some("reencrypt-tokens") {
+                let reencrypted = database
+                    .reencrypt_tokens()
+                    .await
+                    .wrap_err("failed to re-encrypt tokens")?;
+
+                tracing::info!(
+                    updated = reencrypted.updated,
+                    skipped = reencrypted.skipped,
+                    "re-encrypted tokens with the current key"
+                );
+
+                return Ok(());
+            }
+
+            let mailer = notifications::Mailer::from_environment();
+            let webhooks = webhooks::WebhookSender::new();
+
+            tokio::spawn(router::session::prune_expired_sessions(database.clone()));
+
             let (backup, router) = tokio::join!(
-                tokio::spawn(backup::backup(database.clone())),
-                tokio::spawn(router::router(database))
+                tokio::spawn(backup::backup(
+                    database.clone(),
+                    mailer.clone(),
+                    webhooks.clone()
+                )),
+                tokio::spawn(router::router(database, mailer, webhooks))
             );
 
             // FIXME: stupid
@@ -0,0 +1,183 @@
+use futures::Future;
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use rspotify::{http::HttpError, ClientError, ClientResult};
+
+/// Registry of all metrics exposed on `/metrics`
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+fn register<M>(metric: M) -> M
+where
+    M: prometheus::core::Collector + Clone + 'static,
+{
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric should only be registered once");
+
+    metric
+}
+
+pub static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static HTTP_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time until the response head was ready, by route and status",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static BACKUP_RUNS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("backup_runs_total", "Finished backup runs by outcome"),
+            &["outcome"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static BACKUP_RUN_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "backup_run_duration_seconds",
+                "Duration of backup runs by outcome",
+            )
+            // 1 second up to about 68 minutes
+            .buckets(exponential_buckets(1.0, 2.0, 13).expect("histogram buckets should be valid")),
+            &["outcome"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static BACKUPS_IN_PROGRESS: Lazy<IntGauge> = Lazy::new(|| {
+    register(
+        IntGauge::new("backups_in_progress", "Backup runs currently in progress")
+            .expect("metric should be valid"),
+    )
+});
+
+pub static BACKUP_REQUESTS_PENDING: Lazy<IntGauge> = Lazy::new(|| {
+    register(
+        IntGauge::new(
+            "backup_requests_pending",
+            "Backups requested outside of the hourly schedule that have not finished",
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static UPSTREAM_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "upstream_requests_total",
+                "Requests to the spotify and github apis",
+            ),
+            &["service"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static UPSTREAM_RATE_LIMITED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "upstream_rate_limited_total",
+                "Requests to the spotify and github apis that were rate limited",
+            ),
+            &["service"],
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static DATABASE_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register(
+        IntGauge::new(
+            "database_pool_connections",
+            "Open connections of the database pool",
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+pub static DATABASE_IDLE_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register(
+        IntGauge::new(
+            "database_pool_idle_connections",
+            "Idle connections of the database pool",
+        )
+        .expect("metric should be valid"),
+    )
+});
+
+/// All metrics in the prometheus text format
+pub fn render() -> Result<String, prometheus::Error> {
+    // Metrics are registered when first used, make sure all of them show up
+    Lazy::force(&HTTP_REQUESTS);
+    Lazy::force(&HTTP_REQUEST_DURATION);
+    Lazy::force(&BACKUP_RUNS);
+    Lazy::force(&BACKUP_RUN_DURATION);
+    Lazy::force(&BACKUPS_IN_PROGRESS);
+    Lazy::force(&BACKUP_REQUESTS_PENDING);
+    Lazy::force(&UPSTREAM_REQUESTS);
+    Lazy::force(&UPSTREAM_RATE_LIMITED);
+    Lazy::force(&DATABASE_CONNECTIONS);
+    Lazy::force(&DATABASE_IDLE_CONNECTIONS);
+
+    TextEncoder::new().encode_to_string(&REGISTRY.gather())
+}
+
+/// Count a request to the github api
+pub async fn github_request<T>(
+    request: impl Future<Output = octocrab::Result<T>>,
+) -> octocrab::Result<T> {
+    let result = request.await;
+
+    UPSTREAM_REQUESTS.with_label_values(&["github"]).inc();
+    // Github answers rate limited requests with 403 or 429 and a message
+    // explaining which limit was exceeded
+    if let Err(octocrab::Error::GitHub { source, .. }) = &result {
+        if source.message.contains("rate limit") {
+            UPSTREAM_RATE_LIMITED.with_label_values(&["github"]).inc();
+        }
+    }
+
+    result
+}
+
+/// Count a request to the spotify api
+pub async fn spotify_request<T>(request: impl Future<Output = ClientResult<T>>) -> ClientResult<T> {
+    let result = request.await;
+
+    UPSTREAM_REQUESTS.with_label_values(&["spotify"]).inc();
+    if let Err(ClientError::Http(error)) = &result {
+        if let HttpError::StatusCode(response) = error.as_ref() {
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                UPSTREAM_RATE_LIMITED.with_label_values(&["spotify"]).inc();
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,731 @@
+use std::fmt::Write;
+
+use lettre::{
+    message::{
+        header::{ContentType, Header, HeaderName, HeaderValue},
+        Mailbox,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use rspotify::{clients::OAuthClient, http::HttpError};
+use sea_orm::prelude::Uuid;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    backup::{diff::TrackSummary, BackupOutcome, BackupRun},
+    database::{id::AccountId, Database},
+    environment::{EMAIL_ENVIRONMENT, GITHUB_ENVIRONMENT, HTTP_ENVIRONMENT},
+    internal_server_error,
+    pages::{format_date, InternalServerError},
+    router::authentication::Account,
+};
+
+/// Number of backups in a row that have to fail before the user is notified
+pub const FAILURE_THRESHOLD: usize = 3;
+
+const WEEKLY_SUMMARY_INTERVAL: time::Duration = time::Duration::weeks(1);
+
+/// Maximum number of tracks listed in a single email
+const MAX_LISTED_TRACKS: usize = 50;
+
+/// Wait between confirmation emails, so that saving the settings over and
+/// over can not be used to flood an address
+const CONFIRMATION_INTERVAL: time::Duration = time::Duration::minutes(10);
+
+/// How long the link in a confirmation email can be used
+pub const CONFIRMATION_VALIDITY: time::Duration = time::Duration::days(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    BackupFailures,
+    AuthorizationRevoked,
+    WeeklySummary,
+    UnavailableTracks,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [
+        NotificationKind::BackupFailures,
+        NotificationKind::AuthorizationRevoked,
+        NotificationKind::WeeklySummary,
+        NotificationKind::UnavailableTracks,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::BackupFailures => "backup_failures",
+            NotificationKind::AuthorizationRevoked => "authorization_revoked",
+            NotificationKind::WeeklySummary => "weekly_summary",
+            NotificationKind::UnavailableTracks => "unavailable_tracks",
+        }
+    }
+
+    pub const fn description(&self) -> &'static str {
+        match self {
+            NotificationKind::BackupFailures => "backups failing repeatedly",
+            NotificationKind::AuthorizationRevoked => "spotify or github access being revoked",
+            NotificationKind::WeeklySummary => "a weekly summary of library changes",
+            NotificationKind::UnavailableTracks => "tracks becoming unavailable",
+        }
+    }
+}
+
+/// Which emails an account has opted into, and where to send them
+#[derive(Debug, Clone)]
+pub struct NotificationSettings {
+    pub account: AccountId,
+    pub email: String,
+    pub backup_failures: bool,
+    pub authorization_revoked: bool,
+    pub weekly_summary: bool,
+    pub unavailable_tracks: bool,
+    pub unsubscribe_token: Uuid,
+    pub last_weekly_summary: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+    /// Nothing is sent to the address before its owner confirmed it
+    pub email_confirmed_at: Option<OffsetDateTime>,
+    pub confirmation_token: Option<Uuid>,
+    pub confirmation_sent_at: Option<OffsetDateTime>,
+}
+
+impl NotificationSettings {
+    pub fn new(account: AccountId, email: String) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            account,
+            email,
+            backup_failures: false,
+            authorization_revoked: false,
+            weekly_summary: false,
+            unavailable_tracks: false,
+            unsubscribe_token: Uuid::new_v4(),
+            // The first summary is sent a week after subscribing
+            last_weekly_summary: Some(now),
+            created_at: now,
+            email_confirmed_at: None,
+            confirmation_token: None,
+            confirmation_sent_at: None,
+        }
+    }
+
+    /// A new address has to be confirmed again
+    pub fn change_email(&mut self, email: String) {
+        if self.email != email {
+            self.email = email;
+            self.email_confirmed_at = None;
+            self.confirmation_token = None;
+        }
+    }
+
+    pub const fn email_confirmed(&self) -> bool {
+        self.email_confirmed_at.is_some()
+    }
+
+    pub const fn enabled(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::BackupFailures => self.backup_failures,
+            NotificationKind::AuthorizationRevoked => self.authorization_revoked,
+            NotificationKind::WeeklySummary => self.weekly_summary,
+            NotificationKind::UnavailableTracks => self.unavailable_tracks,
+        }
+    }
+
+    /// Link that turns off one kind of notification without having to log in
+    pub fn unsubscribe_url(&self, kind: NotificationKind) -> String {
+        format!(
+            "https://{}/unsubscribe/{}?kind={}",
+            HTTP_ENVIRONMENT.domain,
+            self.unsubscribe_token,
+            kind.as_str()
+        )
+    }
+
+    pub fn from_model(model: entity::notification_settings::Model) -> Self {
+        Self {
+            account: AccountId::from_notification_settings(&model),
+            email: model.email,
+            backup_failures: model.backup_failures,
+            authorization_revoked: model.authorization_revoked,
+            weekly_summary: model.weekly_summary,
+            unavailable_tracks: model.unavailable_tracks,
+            unsubscribe_token: model.unsubscribe_token,
+            last_weekly_summary: model.last_weekly_summary,
+            created_at: model.created_at,
+            email_confirmed_at: model.email_confirmed_at,
+            confirmation_token: model.confirmation_token,
+            confirmation_sent_at: model.confirmation_sent_at,
+        }
+    }
+
+    pub fn into_model(self) -> entity::notification_settings::Model {
+        entity::notification_settings::Model {
+            account: self.account.into_uuid(),
+            email: self.email,
+            backup_failures: self.backup_failures,
+            authorization_revoked: self.authorization_revoked,
+            weekly_summary: self.weekly_summary,
+            unavailable_tracks: self.unavailable_tracks,
+            unsubscribe_token: self.unsubscribe_token,
+            last_weekly_summary: self.last_weekly_summary,
+            created_at: self.created_at,
+            email_confirmed_at: self.email_confirmed_at,
+            confirmation_token: self.confirmation_token,
+            confirmation_sent_at: self.confirmation_sent_at,
+        }
+    }
+}
+
+/// The contents of a notification, before it is addressed to anyone
+#[derive(Debug)]
+pub struct Email {
+    pub kind: NotificationKind,
+    pub subject: String,
+    pub body: String,
+}
+
+/// `List-Unsubscribe` header (RFC 2369), lets mail clients offer their own
+/// unsubscribe button
+#[derive(Debug, Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// `List-Unsubscribe-Post` header (RFC 8058), signals that the unsubscribe
+/// link accepts a one-click POST request
+#[derive(Debug, Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    /// None when email is turned off
+    smtp: Option<Smtp>,
+}
+
+#[derive(Clone)]
+struct Smtp {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(smtp_url: &str, from: Mailbox) -> Result<Self, lettre::transport::smtp::Error> {
+        Ok(Self {
+            smtp: Some(Smtp {
+                transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)?.build(),
+                from,
+            }),
+        })
+    }
+
+    /// A mailer that sends nothing
+    pub const fn disabled() -> Self {
+        Self { smtp: None }
+    }
+
+    pub fn from_environment() -> Self {
+        match EMAIL_ENVIRONMENT.as_ref() {
+            Some(environment) => Self::new(
+                environment.smtp_url.expose_secret(),
+                environment.from.clone(),
+            )
+            .expect("$SMTP_URL should be a valid smtp connection url"),
+            None => {
+                tracing::warn!("$SMTP_URL is not set, email notifications are turned off");
+
+                Self::disabled()
+            }
+        }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.smtp.is_some()
+    }
+
+    #[tracing::instrument(skip_all, fields(kind = email.kind.as_str()))]
+    pub async fn send(
+        &self,
+        to: Mailbox,
+        unsubscribe_url: &str,
+        email: Email,
+    ) -> Result<(), InternalServerError> {
+        let Some(smtp) = &self.smtp else {
+            return Ok(());
+        };
+
+        let body = format!(
+            "{}\n\n-- \nYou are receiving this email because you asked to be notified about {}.\nUnsubscribe: {unsubscribe_url}\n",
+            email.body,
+            email.kind.description(),
+        );
+
+        let message = Message::builder()
+            .from(smtp.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .header(ListUnsubscribe(format!("<{unsubscribe_url}>")))
+            .header(ListUnsubscribePost)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(InternalServerError::from_error)?;
+
+        InternalServerError::wrap_in_current_span(smtp.transport.send(message)).await?;
+
+        Ok(())
+    }
+
+    /// Ask the owner of the address to confirm that they want the emails, the
+    /// only email that is sent to an address before it is confirmed
+    #[tracing::instrument(skip_all, fields(account = %settings.account))]
+    pub async fn send_confirmation(
+        &self,
+        settings: &NotificationSettings,
+    ) -> Result<(), InternalServerError> {
+        let Some(smtp) = &self.smtp else {
+            return Ok(());
+        };
+
+        let token = settings
+            .confirmation_token
+            .ok_or_else(|| internal_server_error!("confirmation token should be set"))?;
+        let to: Mailbox = settings
+            .email
+            .parse()
+            .map_err(InternalServerError::from_error)?;
+
+        let body = format!(
+            "Someone asked Spotify Backup to send emails about the backups of their Spotify library to this address.\n\n\
+            If that was you, confirm the address within {} hours at https://{}/notifications/confirm/{token}\n\n\
+            If it was not, ignore this email. Nothing else is sent to this address unless it is confirmed.\n",
+            CONFIRMATION_VALIDITY.whole_hours(),
+            HTTP_ENVIRONMENT.domain,
+        );
+
+        let message = Message::builder()
+            .from(smtp.from.clone())
+            .to(to)
+            .subject("Confirm your email address for Spotify Backup")
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(InternalServerError::from_error)?;
+
+        InternalServerError::wrap_in_current_span(smtp.transport.send(message)).await?;
+
+        Ok(())
+    }
+
+    async fn send_to(
+        &self,
+        settings: &NotificationSettings,
+        email: Email,
+    ) -> Result<(), InternalServerError> {
+        if !settings.email_confirmed() {
+            tracing::debug!("email address is not confirmed... skipping");
+
+            return Ok(());
+        }
+
+        let to = settings
+            .email
+            .parse()
+            .map_err(InternalServerError::from_error)?;
+        let unsubscribe_url = settings.unsubscribe_url(email.kind);
+
+        self.send(to, &unsubscribe_url, email).await
+    }
+}
+
+/// Save the settings, and send a confirmation email if the address has not
+/// been confirmed yet and no confirmation was sent recently
+pub async fn save_settings(
+    database: &Database,
+    mailer: &Mailer,
+    mut settings: NotificationSettings,
+) -> Result<NotificationSettings, InternalServerError> {
+    let now = OffsetDateTime::now_utc();
+    let send_confirmation = !settings.email_confirmed()
+        && settings
+            .confirmation_sent_at
+            .filter(|sent_at| now - *sent_at < CONFIRMATION_INTERVAL)
+            .is_none();
+
+    if send_confirmation {
+        settings.confirmation_token = Some(Uuid::new_v4());
+        settings.confirmation_sent_at = Some(now);
+    }
+
+    database
+        .save_notification_settings(settings.clone())
+        .await?;
+
+    if send_confirmation {
+        mailer.send_confirmation(&settings).await?;
+    }
+
+    Ok(settings)
+}
+
+/// Send the emails that a finished backup run warrants, must be called
+/// before the run is recorded
+#[tracing::instrument(skip_all, fields(account = %account.id, run = %run.id))]
+pub async fn notify_backup_run(
+    database: &Database,
+    mailer: &Mailer,
+    account: &Account,
+    run: &BackupRun,
+) -> Result<(), InternalServerError> {
+    if !mailer.is_enabled() {
+        return Ok(());
+    }
+
+    let Some(settings) = database.get_notification_settings(account.id).await? else {
+        return Ok(());
+    };
+    if !settings.email_confirmed() {
+        return Ok(());
+    }
+
+    let mut emails = Vec::new();
+
+    match &run.outcome {
+        BackupOutcome::Failed { .. } => {
+            let previous = database
+                .list_backup_runs(account.id, FAILURE_THRESHOLD as u64)
+                .await?;
+            let consecutive_failures = 1 + previous
+                .iter()
+                .take_while(|run| matches!(run.outcome, BackupOutcome::Failed { .. }))
+                .count();
+
+            // Only look for a revoked authorization once per streak of failures
+            if consecutive_failures == 1 && settings.authorization_revoked {
+                emails.extend(authorization_revoked(account).await);
+            }
+
+            if consecutive_failures == FAILURE_THRESHOLD && settings.backup_failures {
+                emails.push(backup_failures(consecutive_failures, run));
+            }
+        }
+        BackupOutcome::Committed { diff, .. }
+            if settings.unavailable_tracks && !diff.became_unavailable.is_empty() =>
+        {
+            emails.push(unavailable_tracks(&diff.became_unavailable));
+        }
+        BackupOutcome::Committed { .. } | BackupOutcome::Unchanged => {}
+    }
+
+    for email in emails {
+        // The error has already been reported, still send the other emails
+        let _ = mailer.send_to(&settings, email).await;
+    }
+
+    Ok(())
+}
+
+/// Send the weekly summary to every account that is due for one
+#[tracing::instrument(skip_all)]
+pub async fn send_weekly_summaries(
+    database: &Database,
+    mailer: &Mailer,
+) -> Result<(), InternalServerError> {
+    // Summaries are not marked as sent, so they go out once email is turned on
+    if !mailer.is_enabled() {
+        return Ok(());
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let cutoff = now - WEEKLY_SUMMARY_INTERVAL;
+
+    for settings in database.list_due_weekly_summaries(cutoff).await? {
+        let since = settings
+            .last_weekly_summary
+            .map_or(cutoff, |last| last.max(cutoff));
+        let runs = database
+            .list_backup_runs_since(settings.account, since)
+            .await?;
+
+        if let Some(email) = weekly_summary(&runs) {
+            // The error has already been reported, do not hold up other accounts
+            if mailer.send_to(&settings, email).await.is_err() {
+                continue;
+            }
+        }
+
+        database
+            .mark_weekly_summary_sent(settings.account, now)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Internal errors are only shown on the dashboard, the email refers to the
+/// run so that it can be looked up
+fn backup_failures(consecutive_failures: usize, run: &BackupRun) -> Email {
+    Email {
+        kind: NotificationKind::BackupFailures,
+        subject: format!("Your last {consecutive_failures} backups failed"),
+        body: format!(
+            "The last {consecutive_failures} backups of your Spotify library did not succeed. \
+            The most recent attempt was backup run {}.\n\n\
+            Backups will keep being retried every hour. \
+            You can check their status and errors at https://{}/dashboard",
+            run.id, HTTP_ENVIRONMENT.domain
+        ),
+    }
+}
+
+/// Probe both services to find out which, if any, no longer accept the
+/// stored authorization
+async fn authorization_revoked(account: &Account) -> Option<Email> {
+    let mut revoked = Vec::new();
+
+    if let Err(rspotify::ClientError::Http(error)) =
+        account.spotify.as_client().current_user().await
+    {
+        if let HttpError::StatusCode(response) = *error {
+            if matches!(response.status().as_u16(), 400 | 401) {
+                revoked.push("Spotify");
+            }
+        }
+    }
+
+    // Backups only use the installation, the user token may simply have expired
+    if let Some(installation) = account.backup_installation() {
+        let installation = GITHUB_ENVIRONMENT
+            .client
+            .apps()
+            .installation(installation.id)
+            .await;
+
+        if let Err(octocrab::Error::GitHub { source, .. }) = installation {
+            if source.message == "Not Found" {
+                revoked.push("GitHub");
+            }
+        }
+    }
+
+    if revoked.is_empty() {
+        return None;
+    }
+
+    let services = revoked.join(" and ");
+
+    Some(Email {
+        kind: NotificationKind::AuthorizationRevoked,
+        subject: format!("Spotify Backup can no longer access your {services} account"),
+        body: format!(
+            "The access you granted to your {services} account has been revoked or has expired, \
+            so your library can not be backed up anymore.\n\n\
+            Log in again at https://{}/account to resume backups.",
+            HTTP_ENVIRONMENT.domain
+        ),
+    })
+}
+
+fn unavailable_tracks(tracks: &[TrackSummary]) -> Email {
+    let mut body = String::from("These tracks in your library can no longer be played:\n\n");
+
+    for track in tracks.iter().take(MAX_LISTED_TRACKS) {
+        let _ = writeln!(body, "  - {track}");
+    }
+    if tracks.len() > MAX_LISTED_TRACKS {
+        let _ = writeln!(body, "  ... and {} more", tracks.len() - MAX_LISTED_TRACKS);
+    }
+
+    let _ = write!(
+        body,
+        "\nTheir last known details are kept in your backups at https://{}/snapshots",
+        HTTP_ENVIRONMENT.domain
+    );
+
+    Email {
+        kind: NotificationKind::UnavailableTracks,
+        subject: match tracks.len() {
+            1 => String::from("A track in your library became unavailable"),
+            count => format!("{count} tracks in your library became unavailable"),
+        },
+        body,
+    }
+}
+
+/// Nothing is sent for a week without any backups
+fn weekly_summary(runs: &[BackupRun]) -> Option<Email> {
+    if runs.is_empty() {
+        return None;
+    }
+
+    let failed = runs
+        .iter()
+        .filter(|run| matches!(run.outcome, BackupOutcome::Failed { .. }))
+        .count();
+    let changes: Vec<_> = runs
+        .iter()
+        .filter_map(|run| match &run.outcome {
+            BackupOutcome::Committed { summary, .. } => Some((run.started_at, summary)),
+            BackupOutcome::Unchanged | BackupOutcome::Failed { .. } => None,
+        })
+        .collect();
+
+    let mut body = if changes.is_empty() {
+        String::from("Your library did not change this week.\n")
+    } else {
+        let mut body = String::from("Your library changed this week:\n\n");
+        for (date, summary) in changes.iter().rev() {
+            let _ = writeln!(body, "  {}: {summary}", format_date(*date));
+        }
+        body
+    };
+
+    if failed > 0 {
+        let _ = writeln!(body, "\n{failed} of {} backups failed.", runs.len());
+    }
+
+    let _ = write!(
+        body,
+        "\nBrowse your snapshots at https://{}/snapshots",
+        HTTP_ENVIRONMENT.domain
+    );
+
+    Some(Email {
+        kind: NotificationKind::WeeklySummary,
+        subject: match changes.len() {
+            0 => String::from("Your weekly backup summary: no changes"),
+            1 => String::from("Your weekly backup summary: 1 change"),
+            count => format!("Your weekly backup summary: {count} changes"),
+        },
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{Email, Mailer, NotificationKind};
+
+    /// Speak just enough SMTP to accept a single message, returning everything
+    /// the client sent
+    async fn smtp_stand_in(stream: TcpStream) -> String {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut transcript = String::new();
+        let mut in_data = false;
+
+        write
+            .write_all(b"220 localhost ESMTP stand-in\r\n")
+            .await
+            .expect("greeting should be sent");
+
+        while let Some(line) = lines.next_line().await.expect("line should be read") {
+            transcript.push_str(&line);
+            transcript.push('\n');
+
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") || line.starts_with("HELO") {
+                b"250 localhost\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 end data with <CR><LF>.<CR><LF>\r\n"
+            } else if line == "QUIT" {
+                b"221 bye\r\n"
+            } else {
+                b"250 ok\r\n"
+            };
+
+            write.write_all(reply).await.expect("reply should be sent");
+
+            if reply.starts_with(b"250 queued") || reply.starts_with(b"221") {
+                break;
+            }
+        }
+
+        transcript
+    }
+
+    #[tokio::test]
+    async fn delivers_email_with_unsubscribe_link() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let address = listener
+            .local_addr()
+            .expect("listener should have an address");
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("client should connect");
+            smtp_stand_in(stream).await
+        });
+
+        let mailer = Mailer::new(
+            &format!("smtp://{address}"),
+            "Spotify Backup <backup@example.com>"
+                .parse()
+                .expect("mailbox should be valid"),
+        )
+        .expect("mailer should be created");
+
+        mailer
+            .send(
+                "user@example.com".parse().expect("mailbox should be valid"),
+                "https://example.com/unsubscribe/token?kind=backup_failures",
+                Email {
+                    kind: NotificationKind::BackupFailures,
+                    subject: String::from("Your last 3 backups failed"),
+                    body: String::from("The last 3 backups did not succeed."),
+                },
+            )
+            .await
+            .expect("email should be sent");
+
+        let transcript = server.await.expect("stand-in should not panic");
+
+        assert!(transcript.contains("MAIL FROM:<backup@example.com>"));
+        assert!(transcript.contains("RCPT TO:<user@example.com>"));
+        assert!(transcript.contains("Subject: Your last 3 backups failed"));
+        assert!(transcript.contains(
+            "List-Unsubscribe: <https://example.com/unsubscribe/token?kind=backup_failures>"
+        ));
+        assert!(transcript.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        // The body is quoted-printable encoded
+        assert!(transcript
+            .contains("Unsubscribe: https://example.com/unsubscribe/token?kind=3Dbackup_failures"));
+    }
+}
@@ -3,21 +3,54 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use dioxus::prelude::*;
+use time::OffsetDateTime;
 
 use crate::router::middleware::server_information::SERVER_INFO;
 
 mod account;
+mod api_tokens;
+mod confirm_email;
 mod dashboard;
+mod delete_account;
 mod error;
+mod feed;
+mod github;
 mod home;
+mod repository;
+mod sessions;
+mod snapshots;
+mod unsubscribe;
+mod webhooks;
 
 pub use {
     account::account,
+    api_tokens::{api_token_created, api_tokens},
+    confirm_email::{confirm_email, email_confirmation},
     dashboard::dashboard,
-    error::{not_found, panic_error, InternalServerError},
+    delete_account::{account_deleted, delete_account},
+    error::{
+        not_found, panic_error, span_trace_context, ClientError, ErrorDetails, InternalServerError,
+        INTERNAL_SERVER_ERROR_TARGET,
+    },
+    feed::feed,
+    github::github_account_taken,
     home::home,
+    repository::repository,
+    sessions::sessions,
+    snapshots::{snapshot, snapshot_diff, snapshot_playlist, snapshots},
+    unsubscribe::{confirm_unsubscribe, unsubscribe},
+    webhooks::webhooks,
 };
 
+pub fn format_date(date: OffsetDateTime) -> String {
+    format!(
+        "{} {:02}:{:02} UTC",
+        date.date(),
+        date.hour(),
+        date.minute()
+    )
+}
+
 pub struct Page<'e> {
     pub title: LazyNodes<'e, 'e>,
     pub content: LazyNodes<'e, 'e>,
@@ -72,6 +105,9 @@ impl<'e> Page<'e> {
                         a { href: "/dashboard",
                             "Dashboard"
                         }
+                        a { href: "/snapshots",
+                            "Snapshots"
+                        }
                     }
                 }
                 self.content
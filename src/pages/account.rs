@@ -1,17 +1,35 @@
+use axum::extract::State;
 use dioxus::prelude::*;
 use rspotify::prelude::Id;
+use sea_orm::prelude::Uuid;
 use tokio::try_join;
 
-use crate::router::authentication::User;
+use crate::{
+    database::Database,
+    environment::HTTP_ENVIRONMENT,
+    notifications::{Mailer, NotificationKind, NotificationSettings},
+    router::{authentication::User, csrf},
+};
 
-use super::{InternalServerError, Page};
+use super::{format_date, InternalServerError, Page};
 
-pub async fn account(current_user: User) -> Result<Page<'static>, InternalServerError> {
-    let (spotify_user, github_user) = try_join!(
+pub async fn account(
+    State(database): State<Database>,
+    State(mailer): State<Mailer>,
+    current_user: User,
+) -> Result<Page<'static>, InternalServerError> {
+    let (spotify_user, github_user, notification_settings, feed_token) = try_join!(
         current_user.account.spotify_user(),
-        current_user.account.github_user()
+        current_user.account.github_user(),
+        database.get_notification_settings(current_user.account.id),
+        database.feed_token(current_user.account.id)
     )?;
-    let user_complete = github_user.is_some();
+    let has_github = github_user.is_some();
+    let has_repository = current_user.account.backup_installation().is_some();
+    let user_complete = has_github && has_repository;
+
+    let csrf = csrf::token(&current_user.session);
+    let deletion_scheduled_at = current_user.account.deletion_scheduled_at.map(format_date);
 
     let spotify_name = spotify_user
         .display_name
@@ -21,6 +39,19 @@ pub async fn account(current_user: User) -> Result<Page<'static>, InternalServer
         title: rsx! { "Account" },
         content: rsx! {
             h1 { "Account" }
+            if let Some(deletion_scheduled_at) = deletion_scheduled_at {
+                let csrf = csrf.clone();
+
+                rsx! {
+                    section {
+                        p { "your account will be deleted on {deletion_scheduled_at}, backups are paused until then" }
+                        form { action: "/account/deletion/cancel", method: "post",
+                            input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                            button { r#type: "submit", "keep my account" }
+                        }
+                    }
+                }
+            }
             if user_complete {
                 rsx! {
                     p { "manage your account" }
@@ -45,24 +76,24 @@ pub async fn account(current_user: User) -> Result<Page<'static>, InternalServer
                 h2 { "Music source" }
                 li {
                     "spotify authenticated as {spotify_name}"
-                    a { href: "/login/spotify",
+                    a { href: "/login/spotify/switch",
                         "change spotify account"
-                        // TODO: redirect to spotify auth where user is always prompted, to allow for user switching
                     }
                 }
                 h2 { "Backup destination" }
                 li {
                     if let Some(user) = github_user {
                         let github_name = user.login;
+                        let csrf = csrf.clone();
 
                         rsx! {
                             "github authenticated as {github_name}"
-                            a { href: "/login/github",
+                            a { href: "/login/github/switch",
                                 "change github account"
-                                // TODO: redirect to github auth where user is always prompted, to allow for user switching
                             }
-                            a { href: "/logout/github",
-                                "remove github account"
+                            form { action: "/logout/github", method: "post",
+                                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                                button { r#type: "submit", "remove github account" }
                             }
                         }
                     } else { rsx! {
@@ -72,10 +103,61 @@ pub async fn account(current_user: User) -> Result<Page<'static>, InternalServer
                         }
                     }
                 }
+                li {
+                    if !has_github {
+                        rsx! { "add a github account to choose a repository" }
+                    } else if has_repository {
+                        rsx! {
+                            "backing up to a repository"
+                            a { href: "/account/repository",
+                                "change repository"
+                            }
+                        }
+                    } else {
+                        rsx! {
+                            a { href: "/account/repository",
+                                "choose a repository"
+                            }
+                        }
+                    }
+                }
+                h2 { "Notifications" }
+                li {
+                    if mailer.is_enabled() {
+                        notification_settings_form(notification_settings, csrf.clone())
+                    } else {
+                        rsx! { "email notifications are turned off on this server" }
+                    }
+                }
+                h2 { "Integrations" }
+                li {
+                    a { href: "/account/webhooks",
+                        "webhooks"
+                    }
+                }
+                li {
+                    feed_form(feed_token, csrf.clone())
+                }
+                li {
+                    a { href: "/account/tokens",
+                        "api tokens"
+                    }
+                }
                 hr {}
                 li {
-                    a { href: "/logout",
-                        "log out"
+                    a { href: "/account/sessions",
+                        "active sessions"
+                    }
+                }
+                li {
+                    a { href: "/account/export",
+                        "download your data"
+                    }
+                }
+                li {
+                    form { action: "/logout", method: "post",
+                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                        button { r#type: "submit", "log out" }
                     }
                 }
                 li {
@@ -87,3 +169,88 @@ pub async fn account(current_user: User) -> Result<Page<'static>, InternalServer
         },
     })
 }
+
+fn notification_settings_form(
+    settings: Option<NotificationSettings>,
+    csrf: String,
+) -> LazyNodes<'static, 'static> {
+    let email = settings
+        .as_ref()
+        .map(|settings| settings.email.clone())
+        .unwrap_or_default();
+    let unconfirmed = settings
+        .as_ref()
+        .is_some_and(|settings| !settings.email_confirmed());
+
+    let checkboxes = NotificationKind::ALL.map(|kind| {
+        let name = kind.as_str();
+        let description = kind.description();
+
+        if settings
+            .as_ref()
+            .is_some_and(|settings| settings.enabled(kind))
+        {
+            rsx! {
+                label {
+                    input { r#type: "checkbox", name: name, value: "true", checked: "checked" }
+                    "email me about {description}"
+                }
+            }
+        } else {
+            rsx! {
+                label {
+                    input { r#type: "checkbox", name: name, value: "true" }
+                    "email me about {description}"
+                }
+            }
+        }
+    });
+
+    rsx! {
+        form { action: "/account/notifications", method: "post",
+            input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+            label {
+                "email address "
+                input { r#type: "email", name: "email", value: "{email}" }
+            }
+            checkboxes.into_iter()
+            if unconfirmed {
+                rsx! {
+                    p {
+                        "the address is not confirmed yet, nothing is sent to it until you follow the link in the confirmation email. "
+                        "saving again sends a new link."
+                    }
+                }
+            }
+            p { "leave the address empty to stop all emails" }
+            button { r#type: "submit", "save notification settings" }
+        }
+    }
+}
+
+fn feed_form(token: Option<Uuid>, csrf: String) -> LazyNodes<'static, 'static> {
+    match token {
+        Some(token) => {
+            let url = format!("https://{}/feed/{token}", HTTP_ENVIRONMENT.domain);
+
+            rsx! {
+                "atom feed of library changes, anyone with this url can read it "
+                a { href: "{url}", code { "{url}" } }
+                form { action: "/account/feed", method: "post",
+                    input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                    button { r#type: "submit", "generate a new feed url" }
+                }
+                form { action: "/account/feed/delete", method: "post",
+                    input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                    button { r#type: "submit", "disable feed" }
+                }
+            }
+        }
+        None => rsx! {
+            form { action: "/account/feed", method: "post",
+                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                button { r#type: "submit", "create an atom feed of library changes" }
+            }
+        },
+    }
+}
@@ -0,0 +1,129 @@
+use axum::extract::State;
+use dioxus::prelude::*;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{
+    api_tokens::{ApiScope, MAX_API_TOKENS},
+    database::Database,
+    router::{authentication::User, csrf},
+};
+
+use super::{format_date, InternalServerError, Page};
+
+pub async fn api_tokens(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Page<'static>, InternalServerError> {
+    let tokens = database.list_api_tokens(user.account.id).await?;
+    let can_add = tokens.len() < MAX_API_TOKENS;
+    let csrf = csrf::token(&user.session);
+
+    let checkboxes = ApiScope::ALL.map(|scope| {
+        let name = scope.as_str();
+        let description = scope.description();
+
+        rsx! {
+            label {
+                input { r#type: "checkbox", name: "scope", value: name }
+                code { name }
+                " {description}"
+            }
+        }
+    });
+
+    Ok(Page {
+        title: rsx! { "API tokens" },
+        content: rsx! {
+            h1 { "API tokens" }
+            p {
+                "tokens let scripts use the JSON api under "
+                code { "/api/v1" }
+                ", send them in an "
+                code { "Authorization: Bearer" }
+                " header. a token can only do what its scopes allow."
+            }
+            nav {
+                a { href: "/account", "back to account" }
+            }
+            h2 { "Tokens" }
+            if tokens.is_empty() {
+                rsx! { p { "no tokens created" } }
+            }
+            table {
+                thead {
+                    tr {
+                        th { "name" }
+                        th { "scopes" }
+                        th { "created" }
+                        th { "last used" }
+                        th {}
+                    }
+                }
+                tbody {
+                    tokens.into_iter().map(|token| {
+                        let action = format!("/account/tokens/{}/delete", token.id);
+                        let csrf = csrf.clone();
+                        let scopes = token
+                            .scopes
+                            .iter()
+                            .map(ApiScope::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        let created = format_date(token.created_at);
+                        let last_used = token
+                            .last_used_at
+                            .map_or_else(|| String::from("never"), format_date);
+
+                        rsx! {
+                            tr {
+                                td { token.name }
+                                td { code { scopes } }
+                                td { created }
+                                td { last_used }
+                                td {
+                                    form { action: "{action}", method: "post",
+                                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                                        button { r#type: "submit", "revoke" }
+                                    }
+                                }
+                            }
+                        }
+                    })
+                }
+            }
+            if can_add {
+                rsx! {
+                    h2 { "New token" }
+                    form { action: "/account/tokens", method: "post",
+                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                        label {
+                            "name "
+                            input { r#type: "text", name: "name", maxlength: "64", required: "required" }
+                        }
+                        checkboxes.into_iter()
+                        button { r#type: "submit", "create token" }
+                    }
+                }
+            } else {
+                rsx! { p { "you can have at most {MAX_API_TOKENS} tokens" } }
+            }
+        },
+    })
+}
+
+/// Shown once after creating a token, it can not be retrieved afterwards
+pub fn api_token_created(name: String, token: SecretString) -> Page<'static> {
+    let token = token.expose_secret().clone();
+
+    Page {
+        title: rsx! { "API token created" },
+        content: rsx! {
+            h1 { "API token created" }
+            p { "copy the token for {name} now, it is not shown again" }
+            pre { code { token } }
+            nav {
+                a { href: "/account/tokens", "back to api tokens" }
+            }
+        },
+    }
+}
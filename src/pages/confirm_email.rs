@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::*;
+use sea_orm::prelude::Uuid;
+use time::OffsetDateTime;
+
+use crate::{database::Database, notifications::CONFIRMATION_VALIDITY};
+
+use super::{error::ClientError, InternalServerError, Page};
+
+/// Confirmation page, the address is only confirmed on POST so that link
+/// scanners in mail clients do not confirm it by visiting the link
+pub async fn email_confirmation(Path(token): Path<Uuid>) -> Page<'static> {
+    let action = format!("/notifications/confirm/{token}");
+
+    Page {
+        title: rsx! { "Confirm email address" },
+        content: rsx! {
+            h1 { "Confirm email address" }
+            p { "receive emails about the backups of your spotify library at this address?" }
+            form { action: "{action}", method: "post",
+                button { r#type: "submit", "confirm" }
+            }
+        },
+    }
+}
+
+pub async fn confirm_email(
+    State(database): State<Database>,
+    Path(token): Path<Uuid>,
+) -> Result<Response, InternalServerError> {
+    let sent_after = OffsetDateTime::now_utc() - CONFIRMATION_VALIDITY;
+
+    if !database
+        .confirm_notification_email(token, sent_after)
+        .await?
+    {
+        return Ok(ClientError::new(String::from(
+            "this confirmation link is not valid anymore, save your notification settings again to get a new one",
+        ))
+        .into_response());
+    }
+
+    Ok(Page {
+        title: rsx! { "Email address confirmed" },
+        content: rsx! {
+            h1 { "Email address confirmed" }
+            p { "you will now receive the emails you chose" }
+            p {
+                "notifications can be changed from your "
+                a { href: "/account", "account" }
+            }
+        },
+    }
+    .into_response())
+}
@@ -1,15 +1,104 @@
+use std::collections::HashSet;
+
+use axum::extract::State;
 use dioxus::prelude::*;
 
-use crate::{pages::Page, router::authentication::User};
+use crate::{
+    backup::{diff::TrackSummary, BackupOutcome},
+    database::Database,
+    pages::{format_date, InternalServerError, Page},
+    router::authentication::User,
+};
+
+/// Number of backup runs shown on the dashboard
+const RECENT_RUNS: u64 = 48;
+
+pub async fn dashboard(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Page<'static>, InternalServerError> {
+    let runs = database
+        .list_backup_runs(user.account.id, RECENT_RUNS)
+        .await?;
+
+    // Tracks that became unavailable in any of the recent runs, newest first
+    let mut seen = HashSet::new();
+    let unavailable: Vec<TrackSummary> = runs
+        .iter()
+        .filter_map(|run| match &run.outcome {
+            BackupOutcome::Committed { diff, .. } => Some(&diff.became_unavailable),
+            _ => None,
+        })
+        .flatten()
+        .filter(|track| seen.insert(track.id.clone()))
+        .cloned()
+        .collect();
+
+    let has_repository = user.account.backup_installation().is_some();
 
-pub async fn dashboard(auth: User) -> Page<'static> {
-    Page {
+    Ok(Page {
         title: rsx! { "Dashboard" },
         content: rsx! {
-            h1 { "hello, i guess" }
-            pre {
-                "{auth:#?}"
+            h1 { "Dashboard" }
+            if !has_repository {
+                rsx! {
+                    p {
+                        "backups will not run until you "
+                        a { href: "/account", "add a backup destination" }
+                    }
+                }
+            }
+            if !unavailable.is_empty() {
+                rsx! {
+                    h2 { "Unavailable tracks" }
+                    p { "these tracks were playable in an earlier backup but can no longer be played in your market" }
+                    ul {
+                        unavailable.into_iter().map(|track| {
+                            let track = track.to_string();
+
+                            rsx! { li { class: "unavailable", track } }
+                        })
+                    }
+                }
+            }
+            h2 { "Recent backups" }
+            if runs.is_empty() {
+                rsx! { p { "no backups have run yet" } }
+            } else {
+                rsx! {
+                    table {
+                        thead {
+                            tr {
+                                th { "started" }
+                                th { "status" }
+                                th { "changes" }
+                            }
+                        }
+                        tbody {
+                            runs.into_iter().map(|run| {
+                                let started = format_date(run.started_at);
+                                let status = run.outcome.status();
+
+                                let details = match run.outcome {
+                                    BackupOutcome::Committed { commit, summary, .. } => rsx! {
+                                        a { href: "/snapshots/{commit}", summary }
+                                    },
+                                    BackupOutcome::Unchanged => rsx! { "no changes" },
+                                    BackupOutcome::Failed { error } => rsx! { code { error } },
+                                };
+
+                                rsx! {
+                                    tr {
+                                        td { started }
+                                        td { status }
+                                        td { details }
+                                    }
+                                }
+                            })
+                        }
+                    }
+                }
             }
         },
-    }
+    })
 }
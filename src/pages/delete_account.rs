@@ -0,0 +1,86 @@
+use dioxus::prelude::*;
+
+use time::OffsetDateTime;
+
+use crate::router::{
+    authentication::{User, DECREE, DELETION_GRACE_PERIOD, SPOTIFY_APPS_URL},
+    csrf,
+};
+
+use super::{format_date, Page};
+
+pub async fn delete_account(user: User) -> Page<'static> {
+    let csrf = csrf::token(&user.session);
+    let grace_days = DELETION_GRACE_PERIOD.whole_days();
+
+    Page {
+        title: rsx! { "Delete account" },
+        content: rsx! {
+            h1 { "Delete account" }
+            p {
+                "your account, its settings and the history of your backups will be deleted. "
+                "the backup repository on github is yours and is not touched."
+            }
+            p {
+                "the account is deleted after {grace_days} days, and you are logged out everywhere. "
+                "log in again before then to keep it."
+            }
+            p {
+                "access to your github account is revoked once the account is deleted. "
+                "spotify does not let apps revoke their own access, remove it from "
+                a { href: SPOTIFY_APPS_URL, "your spotify apps" }
+                " afterwards."
+            }
+            form { action: "/logout/delete", method: "post",
+                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                label {
+                    "please type the following phrase "
+                    pre { DECREE }
+                    input { r#type: "text", name: "decree", autocomplete: "off", required: "required" }
+                }
+                label {
+                    input { r#type: "checkbox", name: "immediately", value: "true" }
+                    "delete immediately, this can not be undone"
+                }
+                button { r#type: "submit", "delete my account" }
+            }
+            nav {
+                a { href: "/account", "go back" }
+            }
+        },
+    }
+}
+
+/// Shown after deleting, or scheduling the deletion of, an account
+pub fn account_deleted(scheduled_for: Option<OffsetDateTime>) -> Page<'static> {
+    let status = match scheduled_for {
+        Some(scheduled_for) => {
+            let scheduled_for = format_date(scheduled_for);
+
+            rsx! {
+                p { "your account will be deleted on {scheduled_for}, and you have been logged out everywhere." }
+                p { "changed your mind? log in with spotify before then and keep your account from the account page." }
+            }
+        }
+        None => rsx! {
+            p { "your account has been deleted, and access to your github account revoked." }
+        },
+    };
+
+    Page {
+        title: rsx! { "Account deleted" },
+        content: rsx! {
+            h1 { "Account deleted" }
+            status
+            p {
+                "spotify does not let apps revoke their own access. "
+                "to remove it, open "
+                a { href: SPOTIFY_APPS_URL, "your spotify apps" }
+                " and remove spotify backup."
+            }
+            nav {
+                a { href: "/", "home" }
+            }
+        },
+    }
+}
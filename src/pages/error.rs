@@ -9,13 +9,38 @@ use futures::Future;
 use tracing::{Instrument, Span};
 use tracing_error::SpanTrace;
 
-use crate::router::middleware::catch_panic::CaughtPanic;
+use crate::router::middleware::{catch_panic::CaughtPanic, request_id::current_request_id};
 
 use super::Page;
 
+/// Machine readable description of an error response, used in place of the
+/// html page for clients that prefer json (see [`crate::router::middleware::error_format`])
+#[derive(Debug, Clone)]
+pub struct ErrorDetails {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ErrorDetails {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn attach(self, mut response: Response) -> Response {
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
 pub fn not_found(path: &str) -> Response {
-    self::error(
+    let details = ErrorDetails::new("not_found", format!("{path} not found"));
+
+    let response = self::error(
         StatusCode::NOT_FOUND,
+        current_request_id().as_deref(),
         rsx! {
             div {
                 code { path }
@@ -23,7 +48,9 @@ pub fn not_found(path: &str) -> Response {
             }
         },
     )
-    .into_response()
+    .into_response();
+
+    details.attach(response)
 }
 
 pub struct ClientError {
@@ -39,28 +66,41 @@ impl ClientError {
 
 impl IntoResponse for ClientError {
     fn into_response(self) -> Response {
-        self::error(
+        let details = ErrorDetails::new("bad_request", self.message.clone());
+
+        let response = self::error(
             StatusCode::BAD_REQUEST,
+            current_request_id().as_deref(),
             rsx! {
                 div { self.message }
             },
         )
-        .into_response()
+        .into_response();
+
+        details.attach(response)
     }
 }
 
+/// Target of the logs of internal server errors, which are reported to sentry
+/// with their full context instead of as plain log events
+pub const INTERNAL_SERVER_ERROR_TARGET: &str = "spotify_backup::internal_server_error";
+
 #[macro_export]
 macro_rules! internal_server_error {
     ($name:expr, $($field:tt)+) => {
         InternalServerError::throw(
             tracing::error_span!($name, $($field)*),
-            &|| tracing::error!({ $($field)+ }, $name)
+            &|| tracing::error!(
+                target: $crate::pages::INTERNAL_SERVER_ERROR_TARGET,
+                { $($field)+ },
+                $name
+            )
         )
     };
     ($name:expr) => {
         InternalServerError::throw(
             tracing::error_span!($name),
-            &|| tracing::error!($name)
+            &|| tracing::error!(target: $crate::pages::INTERNAL_SERVER_ERROR_TARGET, $name)
         )
     };
 }
@@ -79,8 +119,10 @@ impl std::error::Error for FormatError {}
 pub struct InternalServerError {
     inner_error: Option<Box<dyn std::error::Error + Send + Sync + 'static>>,
     span_trace: SpanTrace,
-    backtrace: Backtrace,
+    // Boxed to keep `Result<_, InternalServerError>` small
+    backtrace: Box<Backtrace>,
     caller: &'static Location<'static>,
+    request_id: Option<String>,
 }
 
 impl InternalServerError {
@@ -89,19 +131,21 @@ impl InternalServerError {
     #[doc = "hidden"]
     pub fn throw(span: Span, error: &dyn Fn()) -> Self {
         let caller = Location::caller();
+        let name = span.metadata().map(|metadata| metadata.name());
 
         // "throw" error
         (error)();
-        // // FIXME: Send more info to sentry somehow
-        // // FIXME: include context?? HOW?
-        // tracing::error!(location=%caller, "{error}");
 
-        Self {
+        let error = Self {
             inner_error: None,
             span_trace: SpanTrace::new(span),
-            backtrace: Backtrace::capture(),
+            backtrace: Box::new(Backtrace::capture()),
             caller,
-        }
+            request_id: current_request_id(),
+        };
+        error.report(name);
+
+        error
     }
 
     #[inline]
@@ -170,24 +214,94 @@ impl InternalServerError {
     where
         E: std::error::Error + Send + Sync + 'static,
     {
-        // FIXME: Send more info to sentry somehow
-        // FIXME: include context?? HOW?
-        tracing::error!(location=%caller, "{error}");
+        tracing::error!(target: INTERNAL_SERVER_ERROR_TARGET, location=%caller, "{error}");
 
-        InternalServerError {
+        let error = InternalServerError {
             inner_error: Some(Box::new(error)),
             span_trace: SpanTrace::capture(),
-            backtrace,
+            backtrace: Box::new(backtrace),
             caller,
+            request_id: current_request_id(),
+        };
+        error.report(None);
+
+        error
+    }
+
+    /// Send the error to sentry, with the chain of inner errors as exceptions
+    /// and the span trace, location and request id as context
+    fn report(&self, message: Option<&str>) {
+        let mut event = match &self.inner_error {
+            Some(error) => sentry::event_from_error(error.as_ref()),
+            None => sentry::protocol::Event {
+                level: sentry::Level::Error,
+                message: message.map(String::from),
+                ..Default::default()
+            },
+        };
+
+        // Backtraces are only captured when enabled with `RUST_BACKTRACE`,
+        // sentry attaches the current one otherwise
+        if let Some(stacktrace) =
+            sentry::integrations::backtrace::parse_stacktrace(&self.backtrace.to_string())
+        {
+            match event.exception.last_mut() {
+                Some(exception) => exception.stacktrace = Some(stacktrace),
+                None => event.stacktrace = Some(stacktrace),
+            }
+        }
+
+        event.culprit = Some(self.caller.to_string());
+        event
+            .tags
+            .insert(String::from("location"), self.caller.to_string());
+        if let Some(request_id) = &self.request_id {
+            event
+                .tags
+                .insert(String::from("request_id"), request_id.clone());
         }
+        event.contexts.insert(
+            String::from("span trace"),
+            span_trace_context(&self.span_trace),
+        );
+
+        sentry::capture_event(event);
     }
 
-    fn inner_error(&self) -> String {
+    pub fn inner_error(&self) -> String {
         match &self.inner_error {
             Some(error) => error.to_string(),
             None => String::from("None"),
         }
     }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+}
+
+/// The spans of a span trace, innermost first, as a sentry context
+pub fn span_trace_context(span_trace: &SpanTrace) -> sentry::protocol::Context {
+    let mut spans = Vec::new();
+    span_trace.with_spans(|metadata, fields| {
+        let location = match (metadata.file(), metadata.line()) {
+            (Some(file), Some(line)) => format!(" at {file}:{line}"),
+            _ => String::new(),
+        };
+
+        spans.push(sentry::protocol::Value::from(format!(
+            "{}::{}{{{fields}}}{location}",
+            metadata.target(),
+            metadata.name()
+        )));
+
+        true
+    });
+
+    let mut context = sentry::protocol::Map::new();
+    context.insert(String::from("spans"), spans.into());
+
+    sentry::protocol::Context::Other(context)
 }
 
 impl Display for InternalServerError {
@@ -195,6 +309,9 @@ impl Display for InternalServerError {
         writeln!(f, "INTERNAL SERVER ERROR")?;
         writeln!(f, "{}", self.inner_error())?;
         writeln!(f, "Location: {}", self.caller)?;
+        if let Some(request_id) = &self.request_id {
+            writeln!(f, "Request: {request_id}")?;
+        }
         writeln!(f, "{}", self.span_trace)
     }
 }
@@ -202,8 +319,14 @@ impl std::error::Error for InternalServerError {}
 
 impl IntoResponse for InternalServerError {
     fn into_response(self) -> Response {
-        self::error(
+        // The details of the error are only shown on the debug html page
+        let details = ErrorDetails::new("internal_server_error", "internal server error");
+
+        let request_id = self.request_id.clone();
+
+        let response = self::error(
             StatusCode::INTERNAL_SERVER_ERROR,
+            request_id.as_deref(),
             if cfg!(debug_assertions) {
                 rsx! {
                     h3 { "Error" }
@@ -225,13 +348,20 @@ impl IntoResponse for InternalServerError {
                 rsx! {""}
             },
         )
-        .into_response()
+        .into_response();
+
+        details.attach(response)
     }
 }
 
 pub fn panic_error(panic_info: CaughtPanic) -> Response {
-    self::error(
+    let details = ErrorDetails::new("internal_server_error", "internal server error");
+
+    let request_id = panic_info.request_id().map(String::from);
+
+    let response = self::error(
         StatusCode::INTERNAL_SERVER_ERROR,
+        request_id.as_deref(),
         if cfg!(debug_assertions) {
             rsx! {
                 div { "The application panicked." }
@@ -261,10 +391,16 @@ pub fn panic_error(panic_info: CaughtPanic) -> Response {
             rsx! {""}
         },
     )
-    .into_response()
+    .into_response();
+
+    details.attach(response)
 }
 
-fn error<'a>(status: StatusCode, body: LazyNodes<'a, 'a>) -> (StatusCode, Page<'a>) {
+fn error<'a>(
+    status: StatusCode,
+    request_id: Option<&'a str>,
+    body: LazyNodes<'a, 'a>,
+) -> (StatusCode, Page<'a>) {
     let status_code = status.as_u16();
     let status_reason = status.canonical_reason().unwrap_or("Unknown Error");
 
@@ -279,6 +415,15 @@ fn error<'a>(status: StatusCode, body: LazyNodes<'a, 'a>) -> (StatusCode, Page<'
                 }
                 main {
                     body
+                    if let Some(request_id) = request_id {
+                        rsx! {
+                            p {
+                                class: "reference",
+                                "reference: "
+                                code { request_id }
+                            }
+                        }
+                    }
                 },
                 nav {
                     a { href: "/", "return home" }
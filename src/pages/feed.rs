@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, State},
+    http::{self, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use sea_orm::prelude::Uuid;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{backup::BackupOutcome, database::Database, environment::HTTP_ENVIRONMENT};
+
+use super::{not_found, snapshots::diff_view, InternalServerError};
+
+/// Number of backup runs included in the feed
+const FEED_ENTRIES: u64 = 50;
+
+/// Atom feed with an entry for every backup that changed the library, readable
+/// by anyone with the secret url so that feed readers do not need to log in
+pub async fn feed(
+    State(database): State<Database>,
+    Path(token): Path<Uuid>,
+) -> Result<Response, InternalServerError> {
+    let Some(account) = database.find_account_by_feed_token(token).await? else {
+        return Ok(not_found("/feed"));
+    };
+
+    let runs = database
+        .list_changed_backup_runs(account, FEED_ENTRIES)
+        .await?;
+
+    let domain = &HTTP_ENVIRONMENT.domain;
+    let updated = runs
+        .first()
+        .map_or(OffsetDateTime::UNIX_EPOCH, |run| run.finished_at);
+
+    let mut feed = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    let _ = write!(
+        feed,
+        r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Spotify Backup: library changes</title><id>urn:uuid:{token}</id><updated>{}</updated><link rel="self" href="https://{domain}/feed/{token}"/><link rel="alternate" type="text/html" href="https://{domain}/snapshots"/><author><name>Spotify Backup</name></author>"#,
+        rfc3339(updated),
+    );
+
+    for run in runs {
+        let BackupOutcome::Committed {
+            commit,
+            summary,
+            diff,
+        } = run.outcome
+        else {
+            continue;
+        };
+
+        let content = dioxus_ssr::render_lazy(diff_view(*diff));
+
+        let _ = write!(
+            feed,
+            r#"<entry><title>{}</title><id>urn:uuid:{}</id><updated>{}</updated><link rel="alternate" type="text/html" href="https://{domain}/snapshots/{}"/><content type="html">{}</content></entry>"#,
+            escape(&summary),
+            run.id,
+            rfc3339(run.finished_at),
+            escape(&commit),
+            escape(&content),
+        );
+    }
+
+    feed.push_str("</feed>");
+
+    let headers = [(
+        http::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/atom+xml; charset=utf-8"),
+    )];
+
+    Ok((headers, feed).into_response())
+}
+
+fn rfc3339(date: OffsetDateTime) -> String {
+    date.format(&Rfc3339)
+        .expect("timestamps from the database should be formattable")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,43 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::*;
+use octocrab::models::UserId;
+
+use super::Page;
+
+/// Shown when the github account a user authorized is already linked to
+/// another account, nothing has been changed at that point
+pub fn github_account_taken(github_id: UserId, csrf: &str) -> Response {
+    let github_id = github_id.0.to_string();
+
+    let page = Page {
+        title: rsx! { "GitHub account already linked" },
+        content: rsx! {
+            h1 { "GitHub account already linked" }
+            p {
+                "the github account you authorized is already linked to another spotify backup account, "
+                "most likely one you created by logging in with a different spotify account."
+            }
+            p {
+                "a github account can only be linked to one account, nothing has been changed."
+            }
+            p {
+                "you can move the github account over to this account. "
+                "github will ask you to authorize it again to confirm it is yours, "
+                "and the other account will stop backing up to it."
+            }
+            form { action: "/login/github/transfer", method: "post",
+                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                input { r#type: "hidden", name: "github", value: "{github_id}" }
+                button { r#type: "submit", "move github account to this account" }
+            }
+            p {
+                a { href: "/account", "keep it on the other account" }
+            }
+        },
+    };
+
+    (StatusCode::CONFLICT, page).into_response()
+}
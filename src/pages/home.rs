@@ -1,17 +1,25 @@
 use dioxus::prelude::*;
 
-use crate::router::authentication::User;
+use crate::router::{authentication::User, csrf};
 
 use super::Page;
 
 // FIXME: too many different routes/cases.
 pub async fn home(account: Option<User>) -> Page<'static> {
+    let csrf = account.as_ref().map(|user| csrf::token(&user.session));
+    let logout = rsx! {
+        form { action: "/logout", method: "post",
+            input { r#type: "hidden", name: "csrf", value: "{csrf.unwrap_or_default()}" }
+            button { r#type: "submit", "log out" }
+        }
+    };
+
     let navigation = match account {
-        Some(user) if user.account.github.is_some() => rsx! {
+        Some(user) if user.account.backup_installation().is_some() => rsx! {
             section {
                 h2 { "Welcome back" }
                 ul {
-                    li { a { href: "/logout", "log out" } }
+                    li { logout }
                     li { a { href: "/dashboard", "go to dashboard" } }
                 }
                 pre { "{user.account:#?}" }
@@ -21,7 +29,7 @@ pub async fn home(account: Option<User>) -> Page<'static> {
             section {
                 h2 { "Welcome" }
                 ul {
-                    li { a { href: "/logout", "log out" } }
+                    li { logout }
                     li { a { href: "/account", "finish setting up your account" } }
                 }
                 pre { "{user.account:#?}" }
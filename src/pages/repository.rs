@@ -0,0 +1,77 @@
+use axum::response::{IntoResponse, Redirect, Response};
+use dioxus::prelude::*;
+
+use crate::router::{authentication::User, csrf};
+
+use super::{InternalServerError, Page};
+
+/// Choose which of the repositories the github app is installed on receives
+/// the backups, github also sends users here after installing the app
+pub async fn repository(user: User) -> Result<Response, InternalServerError> {
+    let Some(github) = &user.account.github else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let current = github.installation;
+    let csrf = csrf::token(&user.session);
+    let repositories = github.installed_repositories().await?;
+
+    Ok(Page {
+        title: rsx! { "Backup repository" },
+        content: rsx! {
+            h1 { "Backup repository" }
+            p {
+                "snapshots are committed to a single repository. "
+                "the app can only write to repositories you install it on, and only to the one you choose here."
+            }
+            nav {
+                a { href: "/account", "back to account" }
+            }
+            if repositories.is_empty() {
+                rsx! {
+                    p { "the app is not installed on any repository you have access to yet" }
+                }
+            } else {
+                rsx! {
+                    ul {
+                        repositories.into_iter().map(|installed| {
+                            let name = installed
+                                .repository
+                                .full_name
+                                .unwrap_or(installed.repository.name);
+                            let installation = installed.installation.0.to_string();
+                            let repository = installed.repository.id.0.to_string();
+                            let csrf = csrf.clone();
+                            let selected = current.is_some_and(|current| {
+                                current.id == installed.installation
+                                    && current.repository == installed.repository.id
+                            });
+
+                            rsx! {
+                                li {
+                                    form { action: "/account/repository", method: "post",
+                                        "{name} "
+                                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                                        input { r#type: "hidden", name: "installation", value: "{installation}" }
+                                        input { r#type: "hidden", name: "repository", value: "{repository}" }
+                                        if selected {
+                                            rsx! { "(backing up here)" }
+                                        } else {
+                                            rsx! { button { r#type: "submit", "back up here" } }
+                                        }
+                                    }
+                                }
+                            }
+                        })
+                    }
+                }
+            }
+            p {
+                a { href: "/login/github/install",
+                    "install the app on another repository"
+                }
+            }
+        },
+    }
+    .into_response())
+}
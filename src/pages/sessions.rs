@@ -0,0 +1,89 @@
+use axum::extract::State;
+use dioxus::prelude::*;
+
+use crate::{
+    database::{id::UserSessionId, Database},
+    router::{authentication::User, csrf},
+};
+
+use super::{format_date, InternalServerError, Page};
+
+pub async fn sessions(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Page<'static>, InternalServerError> {
+    let current = UserSessionId::from_model(user.session.clone());
+    let sessions = database.list_user_sessions(user.account.id).await?;
+    let has_others = sessions.len() > 1;
+    let csrf = csrf::token(&user.session);
+
+    Ok(Page {
+        title: rsx! { "Active sessions" },
+        content: rsx! {
+            h1 { "Active sessions" }
+            p {
+                "every browser you logged in with. "
+                "sessions are logged out after two weeks without use, and three months after logging in."
+            }
+            nav {
+                a { href: "/account", "back to account" }
+            }
+            table {
+                thead {
+                    tr {
+                        th { "device" }
+                        th { "network" }
+                        th { "logged in" }
+                        th { "last used" }
+                        th {}
+                    }
+                }
+                tbody {
+                    sessions.into_iter().map(|session| {
+                        let is_current = session.id == current.into_uuid();
+                        let action = format!(
+                            "/account/sessions/{}/logout",
+                            UserSessionId::from_model(session.clone()).public_handle()
+                        );
+                        let device = session.user_agent.unwrap_or_else(|| String::from("unknown"));
+                        let network = session.ip_address.unwrap_or_else(|| String::from("unknown"));
+                        let created = format_date(session.created_at);
+                        let last_seen = format_date(session.last_seen);
+                        let csrf = csrf.clone();
+
+                        rsx! {
+                            tr {
+                                td { device }
+                                td { code { network } }
+                                td { created }
+                                td { last_seen }
+                                td {
+                                    if is_current {
+                                        rsx! { "this session" }
+                                    } else {
+                                        rsx! {
+                                            form { action: "{action}", method: "post",
+                                                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                                                button { r#type: "submit", "log out" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    })
+                }
+            }
+            if has_others {
+                let csrf = csrf.clone();
+
+                rsx! {
+                    form { action: "/account/sessions/logout-others", method: "post",
+                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                        button { r#type: "submit", "log out everywhere else" }
+                    }
+                }
+            }
+        },
+    })
+}
@@ -0,0 +1,424 @@
+use axum::{
+    extract::{Path, Query},
+    response::{IntoResponse, Redirect, Response},
+};
+use dioxus::prelude::*;
+use rspotify::{
+    model::{PlayableItem, PlaylistId},
+    prelude::Id,
+};
+use serde::Deserialize;
+
+use crate::{
+    backup::{
+        diff::{SnapshotDiff, TrackSummary, TracksDiff},
+        github::{is_commit_sha, BackupRepository, HISTORY_PAGE_SIZE},
+        snapshot::{is_playable, is_playable_item},
+    },
+    router::authentication::User,
+};
+
+use super::{error::ClientError, format_date, InternalServerError, Page};
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    page: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    from: String,
+    to: String,
+}
+
+async fn repository(user: &User) -> Result<Option<BackupRepository>, InternalServerError> {
+    match user.account.backup_installation() {
+        Some(installation) => Ok(Some(
+            BackupRepository::for_installation(installation).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+fn invalid_commit(commit: &str) -> Response {
+    ClientError::new(format!("{commit:?} is not a valid commit")).into_response()
+}
+
+pub async fn snapshots(
+    user: User,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Response, InternalServerError> {
+    let Some(repository) = repository(&user).await? else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let page = query.page.unwrap_or(1).max(1);
+    let history = repository.history(page).await?;
+    // A full page may be followed by an empty one, which is cheaper than
+    // asking github for the number of commits
+    let has_more = history.len() == HISTORY_PAGE_SIZE as usize;
+
+    Ok(Page {
+        title: rsx! { "Snapshots" },
+        content: rsx! {
+            h1 { "Snapshots" }
+            p {
+                "every backup that changed your library, as stored in "
+                code { "{repository}" }
+            }
+            form { action: "/snapshots/compare", method: "get",
+                table {
+                    thead {
+                        tr {
+                            th { "from" }
+                            th { "to" }
+                            th { "date" }
+                            th { "changes" }
+                        }
+                    }
+                    tbody {
+                        history.into_iter().map(|commit| {
+                            let date = commit.date.map(format_date).unwrap_or_default();
+                            let subject = commit.subject().to_string();
+                            let short = short_sha(&commit.sha).to_string();
+
+                            rsx! {
+                                tr {
+                                    td { input { r#type: "radio", name: "from", value: "{commit.sha}" } }
+                                    td { input { r#type: "radio", name: "to", value: "{commit.sha}" } }
+                                    td { date }
+                                    td {
+                                        a { href: "/snapshots/{commit.sha}",
+                                            code { short }
+                                            " {subject}"
+                                        }
+                                    }
+                                }
+                            }
+                        })
+                    }
+                }
+                button { r#type: "submit", "compare" }
+            }
+            nav {
+                if page > 1 {
+                    rsx! { a { href: "/snapshots?page={page - 1}", "newer" } }
+                }
+                if has_more {
+                    rsx! { a { href: "/snapshots?page={page + 1}", "older" } }
+                }
+            }
+        },
+    }
+    .into_response())
+}
+
+pub async fn snapshot(
+    user: User,
+    Path(commit): Path<String>,
+) -> Result<Response, InternalServerError> {
+    if !is_commit_sha(&commit) {
+        return Ok(invalid_commit(&commit));
+    }
+
+    let Some(repository) = repository(&user).await? else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let snapshot = repository.snapshot_at(&commit).await?;
+    let short = short_sha(&commit).to_string();
+
+    let liked_songs_count = snapshot.liked_songs.len();
+    let unavailable_count = snapshot
+        .liked_songs
+        .iter()
+        .filter(|saved| !is_playable(&saved.track))
+        .count()
+        + snapshot
+            .playlists
+            .iter()
+            .flat_map(|playlist| &playlist.items)
+            .filter_map(|item| item.track.as_ref())
+            .filter(|item| !is_playable_item(item))
+            .count();
+    let playlists_count = snapshot.playlists.len();
+
+    let mut playlists = snapshot.playlists;
+    playlists.sort_by(|a, b| a.playlist.name.cmp(&b.playlist.name));
+
+    let title = format!("Snapshot {short}");
+
+    Ok(Page {
+        title: rsx! { title },
+        content: rsx! {
+            h1 { "Snapshot " code { "{short}" } }
+            nav {
+                a { href: "/snapshots", "all snapshots" }
+            }
+            if unavailable_count > 0 {
+                rsx! {
+                    p { class: "unavailable",
+                        "{unavailable_count} tracks in this snapshot could not be played in your market"
+                    }
+                }
+            }
+            h2 { "Playlists ({playlists_count})" }
+            ul {
+                playlists.into_iter().map(|playlist| {
+                    let id = playlist.playlist.id.id().to_string();
+                    let name = playlist.playlist.name;
+                    let tracks = playlist.items.len();
+                    let href = format!("/snapshots/{commit}/playlists/{id}");
+
+                    rsx! {
+                        li {
+                            a { href: "{href}", "{name}" }
+                            " ({tracks} tracks)"
+                        }
+                    }
+                })
+            }
+            h2 { "Liked songs ({liked_songs_count})" }
+            table {
+                thead {
+                    tr {
+                        th { "title" }
+                        th { "artists" }
+                        th { "album" }
+                        th { "added" }
+                    }
+                }
+                tbody {
+                    snapshot.liked_songs.into_iter().map(|saved| {
+                        let summary = TrackSummary::from_saved_track(&saved);
+                        let artists = summary.artists.join(", ");
+                        let added = saved.added_at.format("%Y-%m-%d").to_string();
+                        let class = if is_playable(&saved.track) { "" } else { "unavailable" };
+                        let album = saved.track.album.name;
+
+                        rsx! {
+                            tr { class: "{class}",
+                                td { summary.name }
+                                td { artists }
+                                td { album }
+                                td { added }
+                            }
+                        }
+                    })
+                }
+            }
+        },
+    }
+    .into_response())
+}
+
+pub async fn snapshot_playlist(
+    user: User,
+    Path((commit, playlist)): Path<(String, String)>,
+) -> Result<Response, InternalServerError> {
+    if !is_commit_sha(&commit) {
+        return Ok(invalid_commit(&commit));
+    }
+
+    let Ok(playlist_id) = PlaylistId::from_id(&playlist) else {
+        return Ok(
+            ClientError::new(format!("{playlist:?} is not a valid playlist id")).into_response(),
+        );
+    };
+
+    let Some(repository) = repository(&user).await? else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let playlist = repository.playlist_at(&commit, playlist_id).await?;
+    let short = short_sha(&commit).to_string();
+    let name = playlist.playlist.name;
+    let owner = playlist
+        .playlist
+        .owner
+        .display_name
+        .unwrap_or_else(|| playlist.playlist.owner.id.to_string());
+
+    let title = name.clone();
+
+    Ok(Page {
+        title: rsx! { title },
+        content: rsx! {
+            h1 { "{name}" }
+            p { "by {owner}, as of snapshot " code { "{short}" } }
+            nav {
+                a { href: "/snapshots/{commit}", "back to snapshot" }
+            }
+            table {
+                thead {
+                    tr {
+                        th { "#" }
+                        th { "title" }
+                        th { "artists" }
+                        th { "added" }
+                    }
+                }
+                tbody {
+                    playlist.items.into_iter().enumerate().map(|(index, item)| {
+                        let position = index + 1;
+                        let added = item
+                            .added_at
+                            .map(|added| added.format("%Y-%m-%d").to_string())
+                            .unwrap_or_default();
+                        let (title, artists) = match item.track.as_ref().map(TrackSummary::from_playable) {
+                            Some(summary) => (summary.name, summary.artists.join(", ")),
+                            None => (String::from("unavailable"), String::new()),
+                        };
+                        let local = matches!(&item.track, Some(PlayableItem::Track(track)) if track.is_local);
+                        let class = match &item.track {
+                            Some(item) if is_playable_item(item) => "",
+                            _ => "unavailable",
+                        };
+
+                        rsx! {
+                            tr { class: "{class}",
+                                td { "{position}" }
+                                td {
+                                    title
+                                    if local {
+                                        rsx! { " (local file)" }
+                                    }
+                                }
+                                td { artists }
+                                td { added }
+                            }
+                        }
+                    })
+                }
+            }
+        },
+    }
+    .into_response())
+}
+
+pub async fn snapshot_diff(
+    user: User,
+    Query(CompareQuery { from, to }): Query<CompareQuery>,
+) -> Result<Response, InternalServerError> {
+    for commit in [&from, &to] {
+        if !is_commit_sha(commit) {
+            return Ok(invalid_commit(commit));
+        }
+    }
+
+    let Some(repository) = repository(&user).await? else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let (previous, current) =
+        tokio::try_join!(repository.snapshot_at(&from), repository.snapshot_at(&to))?;
+    let diff = SnapshotDiff::between(&previous, &current);
+
+    let from_short = short_sha(&from).to_string();
+    let to_short = short_sha(&to).to_string();
+
+    let title = format!("Changes {from_short}..{to_short}");
+
+    Ok(Page {
+        title: rsx! { title },
+        content: rsx! {
+            h1 { "Changes" }
+            p {
+                "from "
+                a { href: "/snapshots/{from}", code { "{from_short}" } }
+                " to "
+                a { href: "/snapshots/{to}", code { "{to_short}" } }
+            }
+            nav {
+                a { href: "/snapshots", "all snapshots" }
+            }
+            diff_view(diff)
+        },
+    }
+    .into_response())
+}
+
+pub fn diff_view(diff: SnapshotDiff) -> LazyNodes<'static, 'static> {
+    if diff.is_empty() {
+        return rsx! { p { "no changes" } };
+    }
+
+    let summary = diff.summary();
+
+    rsx! {
+        p { summary }
+        if !diff.liked_songs.is_empty() {
+            rsx! {
+                h2 { "Liked songs" }
+                tracks_diff_view(diff.liked_songs)
+            }
+        }
+        if !diff.playlists_created.is_empty() {
+            rsx! {
+                h2 { "Created playlists" }
+                ul {
+                    diff.playlists_created.into_iter().map(|playlist| rsx! { li { playlist.name } })
+                }
+            }
+        }
+        if !diff.playlists_deleted.is_empty() {
+            rsx! {
+                h2 { "Deleted playlists" }
+                ul {
+                    diff.playlists_deleted.into_iter().map(|playlist| rsx! { li { playlist.name } })
+                }
+            }
+        }
+        if !diff.playlists_renamed.is_empty() {
+            rsx! {
+                h2 { "Renamed playlists" }
+                ul {
+                    diff.playlists_renamed.into_iter().map(|rename| rsx! {
+                        li { "{rename.from} → {rename.to}" }
+                    })
+                }
+            }
+        }
+        if !diff.became_unavailable.is_empty() {
+            rsx! {
+                h2 { "No longer available" }
+                ul {
+                    diff.became_unavailable.into_iter().map(|track| {
+                        let track = track.to_string();
+
+                        rsx! { li { class: "unavailable", track } }
+                    })
+                }
+            }
+        }
+        diff.playlists_modified.into_iter().map(|playlist| rsx! {
+            h2 { "Playlist {playlist.playlist.name}" }
+            tracks_diff_view(playlist.tracks)
+        })
+    }
+}
+
+fn tracks_diff_view(diff: TracksDiff) -> LazyNodes<'static, 'static> {
+    let changes = diff
+        .added
+        .into_iter()
+        .map(|track| ("added", track))
+        .chain(diff.removed.into_iter().map(|track| ("removed", track)))
+        .chain(diff.moved.into_iter().map(|track| ("moved", track)));
+
+    rsx! {
+        ul {
+            changes.map(|(change, track)| {
+                let track = track.to_string();
+
+                rsx! {
+                    li { class: "{change}", "{change}: {track}" }
+                }
+            })
+        }
+    }
+}
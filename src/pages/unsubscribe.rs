@@ -0,0 +1,84 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use dioxus::prelude::*;
+use sea_orm::prelude::Uuid;
+use serde::Deserialize;
+
+use crate::{database::Database, notifications::NotificationKind};
+
+use super::{error::ClientError, InternalServerError, Page};
+
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeQuery {
+    kind: Option<NotificationKind>,
+}
+
+fn invalid_link() -> Response {
+    ClientError::new(String::from("this unsubscribe link is not valid anymore")).into_response()
+}
+
+fn subject(kind: Option<NotificationKind>) -> String {
+    match kind {
+        Some(kind) => format!("emails about {}", kind.description()),
+        None => String::from("all emails"),
+    }
+}
+
+/// Confirmation page, unsubscribing only happens on POST so that link
+/// scanners in mail clients do not unsubscribe by visiting the link
+pub async fn unsubscribe(
+    State(database): State<Database>,
+    Path(token): Path<Uuid>,
+    Query(UnsubscribeQuery { kind }): Query<UnsubscribeQuery>,
+) -> Result<Response, InternalServerError> {
+    let Some(settings) = database.find_notification_settings_by_token(token).await? else {
+        return Ok(invalid_link());
+    };
+
+    let email = settings.email;
+    let subject = subject(kind);
+    let action = match kind {
+        Some(kind) => format!("/unsubscribe/{token}?kind={}", kind.as_str()),
+        None => format!("/unsubscribe/{token}"),
+    };
+
+    Ok(Page {
+        title: rsx! { "Unsubscribe" },
+        content: rsx! {
+            h1 { "Unsubscribe" }
+            p { "stop sending {subject} to {email}?" }
+            form { action: "{action}", method: "post",
+                button { r#type: "submit", "unsubscribe" }
+            }
+        },
+    }
+    .into_response())
+}
+
+/// Also handles one-click unsubscribe requests sent by mail clients
+pub async fn confirm_unsubscribe(
+    State(database): State<Database>,
+    Path(token): Path<Uuid>,
+    Query(UnsubscribeQuery { kind }): Query<UnsubscribeQuery>,
+) -> Result<Response, InternalServerError> {
+    if !database.unsubscribe(token, kind).await? {
+        return Ok(invalid_link());
+    }
+
+    let subject = subject(kind);
+
+    Ok(Page {
+        title: rsx! { "Unsubscribed" },
+        content: rsx! {
+            h1 { "Unsubscribed" }
+            p { "you will no longer receive {subject}" }
+            p {
+                "notifications can be turned back on from your "
+                a { href: "/account", "account" }
+            }
+        },
+    }
+    .into_response())
+}
@@ -0,0 +1,135 @@
+use axum::extract::State;
+use dioxus::prelude::*;
+use secrecy::ExposeSecret;
+
+use crate::{
+    database::Database,
+    router::{authentication::User, csrf},
+    webhooks::{MAX_WEBHOOKS, SIGNATURE_HEADER},
+};
+
+use super::{format_date, InternalServerError, Page};
+
+/// Number of delivery attempts shown on the page
+const RECENT_DELIVERIES: u64 = 20;
+
+pub async fn webhooks(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Page<'static>, InternalServerError> {
+    let (webhooks, secret, deliveries) = tokio::try_join!(
+        database.list_webhooks(user.account.id),
+        database.webhook_secret(user.account.id),
+        database.list_webhook_deliveries(user.account.id, RECENT_DELIVERIES)
+    )?;
+
+    let can_add = webhooks.len() < MAX_WEBHOOKS;
+    let csrf = csrf::token(&user.session);
+
+    Ok(Page {
+        title: rsx! { "Webhooks" },
+        content: rsx! {
+            h1 { "Webhooks" }
+            p {
+                "every registered url receives a JSON "
+                code { "POST" }
+                " request after each backup run, with its status, the number of changes and a summary of the changes"
+            }
+            nav {
+                a { href: "/account", "back to account" }
+            }
+            h2 { "Registered urls" }
+            if webhooks.is_empty() {
+                rsx! { p { "no webhooks registered" } }
+            }
+            ul {
+                webhooks.into_iter().map(|webhook| {
+                    let action = format!("/account/webhooks/{}/delete", webhook.id);
+                    let url = webhook.url.to_string();
+                    let csrf = csrf.clone();
+
+                    rsx! {
+                        li {
+                            code { url }
+                            form { action: "{action}", method: "post",
+                                input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                                button { r#type: "submit", "remove" }
+                            }
+                        }
+                    }
+                })
+            }
+            if can_add {
+                let csrf = csrf.clone();
+
+                rsx! {
+                    form { action: "/account/webhooks", method: "post",
+                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                        label {
+                            "url "
+                            input { r#type: "url", name: "url", placeholder: "https://", required: "required" }
+                        }
+                        button { r#type: "submit", "add webhook" }
+                    }
+                }
+            } else {
+                rsx! { p { "you can register at most {MAX_WEBHOOKS} webhooks" } }
+            }
+            h2 { "Signing secret" }
+            if let Some(secret) = secret {
+                let secret = secret.expose_secret().clone();
+                let csrf = csrf.clone();
+
+                rsx! {
+                    p {
+                        "every request carries an "
+                        code { "{SIGNATURE_HEADER}" }
+                        " header containing "
+                        code { "sha256=" }
+                        " followed by the hex encoded HMAC-SHA256 of the request body, keyed with this secret"
+                    }
+                    pre { code { secret } }
+                    form { action: "/account/webhooks/secret", method: "post",
+                        input { r#type: "hidden", name: "csrf", value: "{csrf}" }
+                        button { r#type: "submit", "generate a new secret" }
+                    }
+                }
+            } else {
+                rsx! { p { "a secret is generated when the first webhook is added" } }
+            }
+            h2 { "Recent deliveries" }
+            table {
+                thead {
+                    tr {
+                        th { "attempted" }
+                        th { "url" }
+                        th { "attempt" }
+                        th { "result" }
+                    }
+                }
+                tbody {
+                    deliveries.into_iter().map(|(delivery, webhook)| {
+                        let attempted = format_date(delivery.attempted_at);
+                        let url = webhook.url.to_string();
+                        let attempt = delivery.attempt;
+                        let class = if delivery.succeeded() { "added" } else { "removed" };
+                        let result = match (delivery.status_code, delivery.error) {
+                            (_, Some(error)) => error,
+                            (Some(status), None) => format!("HTTP {status}"),
+                            (None, None) => String::from("no response"),
+                        };
+
+                        rsx! {
+                            tr {
+                                td { attempted }
+                                td { code { url } }
+                                td { "{attempt}" }
+                                td { class: "{class}", result }
+                            }
+                        }
+                    })
+                }
+            }
+        },
+    })
+}
@@ -1,6 +1,13 @@
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
-use axum::{extract::FromRef, http::header, response::Redirect, routing::get, Router};
+use axum::{
+    extract::FromRef,
+    http::header,
+    response::Redirect,
+    routing::{get, post},
+    Router,
+};
+use axum_extra::extract::cookie::Key;
 use color_eyre::eyre::Context;
 use tower_http::{
     cors::CorsLayer, request_id::MakeRequestUuid, services::ServeDir, timeout::TimeoutLayer,
@@ -11,55 +18,148 @@ use tracing::debug;
 use middleware::{catch_panic::catch_panic_layer, trace::SpanMaker};
 
 use crate::{
-    database::Database, environment::HTTP_ENVIRONMENT, pages,
-    router::middleware::server_information::StaticServerInformation,
+    database::Database, environment::HTTP_ENVIRONMENT, notifications::Mailer, pages,
+    router::middleware::server_information::StaticServerInformation, webhooks::WebhookSender,
 };
 
+pub mod api;
+pub mod api_tokens;
 pub mod authentication;
+pub mod csrf;
 pub mod error;
+pub mod export;
+pub mod feed;
+pub mod health;
+pub mod metrics;
 pub mod middleware;
+pub mod notifications;
 pub mod session;
+pub mod sessions;
+pub mod webhooks;
 
 pub async fn favicon() -> Redirect {
     Redirect::to("/static/branding/logo@192.png")
 }
 
+/// Client for requests to other services, outside of their api clients
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .brotli(true)
+        .gzip(true)
+        .deflate(true)
+        .https_only(true)
+        .use_rustls_tls()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION"),
+        ))
+        .build()
+        .expect("failed to create reqwest client")
+}
+
 #[derive(FromRef, Clone)]
 pub struct AppState {
     pub database: Database,
+    pub cookie_key: Key,
     pub reqwest: reqwest::Client,
+    pub mailer: Mailer,
+    pub webhooks: WebhookSender,
 }
 
-pub async fn router(database: Database) -> color_eyre::Result<()> {
+pub async fn router(
+    database: Database,
+    mailer: Mailer,
+    webhooks: WebhookSender,
+) -> color_eyre::Result<()> {
     let state = AppState {
         database,
-        reqwest: reqwest::Client::builder()
-            .brotli(true)
-            .gzip(true)
-            .deflate(true)
-            .https_only(true)
-            .use_rustls_tls()
-            .user_agent(concat!(
-                env!("CARGO_PKG_NAME"),
-                "/",
-                env!("CARGO_PKG_VERSION"),
-            ))
-            .build()
-            .expect("failed to create reqwest client"),
+        mailer,
+        webhooks,
+        cookie_key: HTTP_ENVIRONMENT.cookie_key.clone(),
+        reqwest: http_client(),
     };
 
     let app = Router::new()
         .route("/", get(pages::home))
         .route("/dashboard", get(pages::dashboard))
         .route("/account", get(pages::account))
+        .route(
+            "/account/repository",
+            get(pages::repository).post(authentication::github::select_repository),
+        )
+        .route(
+            "/account/notifications",
+            post(notifications::update_settings),
+        )
+        .route(
+            "/account/webhooks",
+            get(pages::webhooks).post(webhooks::create),
+        )
+        .route("/account/sessions", get(pages::sessions))
+        .route(
+            "/account/sessions/logout-others",
+            post(sessions::logout_others),
+        )
+        .route("/account/sessions/:session/logout", post(sessions::logout))
+        .route("/account/feed", post(feed::rotate_token))
+        .route("/account/feed/delete", post(feed::disable))
+        .route("/feed/:token", get(pages::feed))
+        .route("/account/webhooks/secret", post(webhooks::rotate_secret))
+        .route("/account/webhooks/:webhook/delete", post(webhooks::delete))
+        .route("/account/export", get(export::export))
+        .route(
+            "/account/tokens",
+            get(pages::api_tokens).post(api_tokens::create),
+        )
+        .route("/account/tokens/:token/delete", post(api_tokens::delete))
+        .route("/api/openapi.json", get(api::openapi))
+        .nest("/api/v1", api::router())
+        .route("/snapshots", get(pages::snapshots))
+        .route("/snapshots/compare", get(pages::snapshot_diff))
+        .route("/snapshots/:commit", get(pages::snapshot))
+        .route(
+            "/snapshots/:commit/playlists/:playlist",
+            get(pages::snapshot_playlist),
+        )
+        .route(
+            "/notifications/confirm/:token",
+            get(pages::email_confirmation).post(pages::confirm_email),
+        )
+        .route(
+            "/unsubscribe/:token",
+            get(pages::unsubscribe).post(pages::confirm_unsubscribe),
+        )
         .route("/login/spotify", get(authentication::spotify::login))
+        .route(
+            "/login/spotify/switch",
+            get(authentication::spotify::switch),
+        )
         .route("/login/github", get(authentication::github::login))
-        .route("/logout", get(authentication::logout))
-        .route("/logout/delete", get(authentication::delete))
-        .route("/logout/github", get(authentication::github::logout))
+        .route("/login/github/switch", get(authentication::github::switch))
+        .route(
+            "/login/github/install",
+            get(authentication::github::install),
+        )
+        .route(
+            "/login/github/transfer",
+            post(authentication::github::transfer),
+        )
+        .route("/logout", post(authentication::logout))
+        .route(
+            "/logout/delete",
+            get(pages::delete_account).post(authentication::delete),
+        )
+        .route("/logout/github", post(authentication::github::logout))
+        .route(
+            "/account/deletion/cancel",
+            post(authentication::cancel_deletion),
+        )
         // TODO: Image resizing/optimization
         .route("/favicon.ico", get(favicon))
-        .route("/health", get(|| async { "OK" }))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/metrics", get(metrics::metrics))
         .nest_service(
             "/static",
             ServeDir::new(&HTTP_ENVIRONMENT.static_dir)
@@ -67,19 +167,30 @@ pub async fn router(database: Database) -> color_eyre::Result<()> {
                 .call_fallback_on_method_not_allowed(true)
                 .fallback(get(error::not_found)),
         )
+        .route_layer(axum::middleware::from_fn(
+            middleware::metrics::expose_matched_path,
+        ))
         .fallback(error::not_found)
         .layer(
             tower::ServiceBuilder::new()
                 // Hide sensitive headers
                 .sensitive_headers([header::AUTHORIZATION, header::COOKIE])
                 // Give a unique identifier to every request
-                .set_x_request_id(MakeRequestUuid) // TODO: USE
+                .set_x_request_id(MakeRequestUuid)
                 .propagate_x_request_id()
                 // Trace requests and responses
                 .layer(TraceLayer::new_for_http().make_span_with(SpanMaker)) // TODO: configure
                 // Send traces to sentry
                 .layer(sentry::integrations::tower::NewSentryLayer::new_from_top())
                 .layer(sentry::integrations::tower::SentryHttpLayer::with_transaction())
+                // Tag errors and sentry events with the request id
+                .layer(axum::middleware::from_fn(
+                    middleware::request_id::scope_request_id,
+                ))
+                // Count requests by route and status
+                .layer(axum::middleware::from_fn(
+                    middleware::metrics::track_requests,
+                ))
                 // Timeout if request or response hangs
                 .layer(TimeoutLayer::new(Duration::from_secs(10)))
                 // Compress responses
@@ -107,6 +218,10 @@ pub async fn router(database: Database) -> color_eyre::Result<()> {
                 .layer(axum::middleware::from_fn(
                     StaticServerInformation::middleware,
                 ))
+                // Render errors as json for clients that ask for it
+                .layer(axum::middleware::from_fn(
+                    middleware::error_format::error_format,
+                ))
                 // Catch Panics in handlers
                 .layer(catch_panic_layer(error::internal_server_error_panic)),
         )
@@ -114,7 +229,7 @@ pub async fn router(database: Database) -> color_eyre::Result<()> {
 
     debug!(bind = ?HTTP_ENVIRONMENT.bind, "started http server");
     axum::Server::bind(&HTTP_ENVIRONMENT.bind)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .wrap_err("failed to bind to given address")
 }
@@ -0,0 +1,627 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::{header, request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::debug;
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+
+use crate::{
+    api_tokens::{hash_token, ApiScope, ApiToken},
+    backup::{
+        self,
+        diff::{
+            PlaylistDiff, PlaylistRename, PlaylistSummary, SnapshotDiff, TrackSummary, TracksDiff,
+        },
+        github::{is_commit_sha, BackupRepository},
+        snapshot::{PlaylistSnapshot, Snapshot},
+        BackupOutcome,
+    },
+    database::Database,
+    notifications::{self, Mailer, NotificationSettings},
+    pages::{ErrorDetails, InternalServerError},
+    webhooks::{DiffCounts, WebhookPayload},
+};
+
+use super::{authentication::Account, middleware::error_format::ErrorBody, AppState};
+
+/// `last_used_at` of a token is only written when it is older than this
+const LAST_USED_INTERVAL: time::Duration = time::Duration::minutes(5);
+
+/// Number of backup runs listed when the request does not say
+const DEFAULT_RUNS_LIMIT: u64 = 20;
+const MAX_RUNS_LIMIT: u64 = 100;
+
+/// The OpenAPI document of the api, generated from the handlers below
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Spotify Backup",
+        description = "Backups of your spotify library",
+        license(name = "MPL-2.0")
+    ),
+    paths(
+        list_backup_runs,
+        start_backup,
+        snapshot,
+        compare_snapshots,
+        notification_settings,
+        update_notification_settings,
+        delete_notification_settings,
+    ),
+    components(schemas(
+        ErrorBody,
+        BackupStarted,
+        WebhookPayload,
+        DiffCounts,
+        Snapshot,
+        PlaylistSnapshot,
+        SnapshotDiff,
+        TracksDiff,
+        TrackSummary,
+        PlaylistSummary,
+        PlaylistRename,
+        PlaylistDiff,
+        NotificationSettingsBody,
+    )),
+    modifiers(&BearerToken),
+    tags(
+        (name = "backups", description = "Backup runs of the account"),
+        (name = "snapshots", description = "The library as stored in the backup repository"),
+        (name = "settings", description = "Settings of the account"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+    }
+}
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/backup-runs", get(list_backup_runs))
+        .route("/backups", post(start_backup))
+        .route("/snapshots/compare", get(compare_snapshots))
+        .route("/snapshots/:commit", get(snapshot))
+        .route(
+            "/settings/notifications",
+            get(notification_settings)
+                .put(update_notification_settings)
+                .delete(delete_notification_settings),
+        )
+}
+
+/// Errors of the json api, rendered as an [`ErrorBody`]
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn unauthorized(message: &str) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    fn code(&self) -> &'static str {
+        match self.status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+            StatusCode::INTERNAL_SERVER_ERROR => "internal_server_error",
+            _ => "error",
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let details = ErrorDetails::new(self.code(), self.message.clone());
+
+        // The request id is filled in by the `error_format` middleware
+        let mut response = (
+            self.status,
+            Json(ErrorBody {
+                code: details.code,
+                message: self.message,
+                request_id: None,
+            }),
+        )
+            .into_response();
+        response.extensions_mut().insert(details);
+
+        if self.status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+        }
+
+        response
+    }
+}
+
+/// The error has already been reported when it was created
+impl From<InternalServerError> for ApiError {
+    fn from(_: InternalServerError) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+    }
+}
+
+/// An account authenticated with a personal api token in the
+/// `Authorization: Bearer` header
+#[derive(Debug)]
+pub struct ApiUser {
+    pub token: ApiToken,
+    pub account: Account,
+}
+
+impl ApiUser {
+    pub fn require(&self, scope: ApiScope) -> Result<(), ApiError> {
+        if self.token.allows(scope) {
+            Ok(())
+        } else {
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!("the token is missing the {} scope", scope.as_str()),
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiUser
+where
+    Database: FromRef<S>,
+    S: Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let database = Database::from_ref(state);
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::unauthorized("missing bearer token"))?;
+
+        let Some(mut token) = database.find_api_token(&hash_token(token.trim())).await? else {
+            return Err(ApiError::unauthorized("invalid token"));
+        };
+
+        let Some(account) = database.get_account(token.account).await? else {
+            return Err(ApiError::unauthorized("invalid token"));
+        };
+
+        if account.deletion_scheduled_at.is_some() {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "the account is scheduled for deletion",
+            ));
+        }
+
+        sentry::configure_scope(|scope| scope.set_tag("account", account.id));
+
+        let now = OffsetDateTime::now_utc();
+        if token
+            .last_used_at
+            .filter(|&last_used_at| now - last_used_at <= LAST_USED_INTERVAL)
+            .is_none()
+        {
+            // Not worth failing the request over
+            if database
+                .touch_api_token(token.id, now, now - LAST_USED_INTERVAL)
+                .await
+                .is_ok()
+            {
+                token.last_used_at = Some(now);
+            }
+        }
+
+        debug!(token = %token.id, account = %account.id, "authenticated api request");
+
+        Ok(ApiUser { token, account })
+    }
+}
+
+async fn repository(account: &Account) -> Result<BackupRepository, ApiError> {
+    match account.backup_installation() {
+        Some(installation) => Ok(BackupRepository::for_installation(installation).await?),
+        None => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "the account has no backup repository yet",
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RunsQuery {
+    /// Number of runs to return, at most 100
+    limit: Option<u64>,
+}
+
+/// Most recent backup runs, newest first
+///
+/// Runs are in the same format as webhook payloads.
+#[utoipa::path(
+    get,
+    path = "/api/v1/backup-runs",
+    tag = "backups",
+    params(RunsQuery),
+    responses(
+        (status = 200, description = "Backup runs", body = Vec<WebhookPayload>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+    ),
+    security(("token" = ["backups:read"]))
+)]
+async fn list_backup_runs(
+    State(database): State<Database>,
+    user: ApiUser,
+    Query(query): Query<RunsQuery>,
+) -> Result<Json<Vec<WebhookPayload>>, ApiError> {
+    user.require(ApiScope::BackupsRead)?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RUNS_LIMIT)
+        .clamp(1, MAX_RUNS_LIMIT);
+    let runs = database.list_backup_runs(user.account.id, limit).await?;
+
+    Ok(Json(runs.iter().map(WebhookPayload::from_run).collect()))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BackupStarted {
+    status: &'static str,
+}
+
+/// Back up now instead of at the next hour
+///
+/// The run shows up in the backup runs once it has finished.
+#[utoipa::path(
+    post,
+    path = "/api/v1/backups",
+    tag = "backups",
+    responses(
+        (status = 202, description = "The backup has started", body = BackupStarted),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+        (status = 409, description = "No backup repository, or a backup is already running", body = ErrorBody),
+    ),
+    security(("token" = ["backups:write"]))
+)]
+async fn start_backup(
+    State(AppState {
+        database,
+        mailer,
+        webhooks,
+        ..
+    }): State<AppState>,
+    user: ApiUser,
+) -> Result<Response, ApiError> {
+    user.require(ApiScope::BackupsWrite)?;
+
+    if user.account.backup_installation().is_none() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "the account has no backup repository yet",
+        ));
+    }
+
+    if !backup::request_backup(database, mailer, webhooks, user.account) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "a backup of the account is already running",
+        ));
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(BackupStarted { status: "started" }),
+    )
+        .into_response())
+}
+
+/// The library as of a commit
+///
+/// `latest` is the most recent snapshot that changed the library.
+#[utoipa::path(
+    get,
+    path = "/api/v1/snapshots/{commit}",
+    tag = "snapshots",
+    params(("commit" = String, Path, description = "Full 40 character commit sha, or `latest`")),
+    responses(
+        (status = 200, description = "The snapshot", body = Snapshot),
+        (status = 400, description = "Invalid commit", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+        (status = 404, description = "There are no snapshots yet", body = ErrorBody),
+        (status = 409, description = "No backup repository", body = ErrorBody),
+    ),
+    security(("token" = ["snapshots:read"]))
+)]
+async fn snapshot(
+    State(database): State<Database>,
+    user: ApiUser,
+    Path(commit): Path<String>,
+) -> Result<Json<Snapshot>, ApiError> {
+    user.require(ApiScope::SnapshotsRead)?;
+
+    let commit = if commit == "latest" {
+        database
+            .list_changed_backup_runs(user.account.id, 1)
+            .await?
+            .into_iter()
+            .find_map(|run| match run.outcome {
+                BackupOutcome::Committed { commit, .. } => Some(commit),
+                _ => None,
+            })
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "there are no snapshots yet"))?
+    } else if is_commit_sha(&commit) {
+        commit
+    } else {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("{commit:?} is not a valid commit"),
+        ));
+    };
+
+    let repository = repository(&user.account).await?;
+
+    Ok(Json(repository.snapshot_at(&commit).await?))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    /// Full 40 character commit sha of the older snapshot
+    from: String,
+    /// Full 40 character commit sha of the newer snapshot
+    to: String,
+}
+
+/// Changes between two snapshots
+#[utoipa::path(
+    get,
+    path = "/api/v1/snapshots/compare",
+    tag = "snapshots",
+    params(CompareQuery),
+    responses(
+        (status = 200, description = "Changes from the older to the newer snapshot", body = SnapshotDiff),
+        (status = 400, description = "Invalid commit", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+        (status = 409, description = "No backup repository", body = ErrorBody),
+    ),
+    security(("token" = ["snapshots:read"]))
+)]
+async fn compare_snapshots(
+    user: ApiUser,
+    Query(CompareQuery { from, to }): Query<CompareQuery>,
+) -> Result<Json<SnapshotDiff>, ApiError> {
+    user.require(ApiScope::SnapshotsRead)?;
+
+    for commit in [&from, &to] {
+        if !is_commit_sha(commit) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("{commit:?} is not a valid commit"),
+            ));
+        }
+    }
+
+    let repository = repository(&user.account).await?;
+    let (previous, current) =
+        tokio::try_join!(repository.snapshot_at(&from), repository.snapshot_at(&to))?;
+
+    Ok(Json(SnapshotDiff::between(&previous, &current)))
+}
+
+/// Notification settings as read and written through the api
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NotificationSettingsBody {
+    email: String,
+    /// Emails are only sent once the address is confirmed through the link
+    /// sent to it, ignored when changing the settings
+    #[serde(default)]
+    #[schema(read_only)]
+    email_confirmed: bool,
+    #[serde(default)]
+    backup_failures: bool,
+    #[serde(default)]
+    authorization_revoked: bool,
+    #[serde(default)]
+    weekly_summary: bool,
+    #[serde(default)]
+    unavailable_tracks: bool,
+}
+
+impl NotificationSettingsBody {
+    fn from_settings(settings: NotificationSettings) -> Self {
+        Self {
+            email_confirmed: settings.email_confirmed(),
+            email: settings.email,
+            backup_failures: settings.backup_failures,
+            authorization_revoked: settings.authorization_revoked,
+            weekly_summary: settings.weekly_summary,
+            unavailable_tracks: settings.unavailable_tracks,
+        }
+    }
+}
+
+/// Email notifications of the account
+///
+/// `null` when the account receives no emails.
+#[utoipa::path(
+    get,
+    path = "/api/v1/settings/notifications",
+    tag = "settings",
+    responses(
+        (status = 200, description = "Notification settings", body = Option<NotificationSettingsBody>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+    ),
+    security(("token" = ["settings:read"]))
+)]
+async fn notification_settings(
+    State(database): State<Database>,
+    user: ApiUser,
+) -> Result<Json<Option<NotificationSettingsBody>>, ApiError> {
+    user.require(ApiScope::SettingsRead)?;
+
+    let settings = database.get_notification_settings(user.account.id).await?;
+
+    Ok(Json(settings.map(NotificationSettingsBody::from_settings)))
+}
+
+/// Change the email notifications of the account
+///
+/// A new address receives a link to confirm it, nothing else is sent to it
+/// until it is confirmed.
+#[utoipa::path(
+    put,
+    path = "/api/v1/settings/notifications",
+    tag = "settings",
+    request_body = NotificationSettingsBody,
+    responses(
+        (status = 200, description = "The saved notification settings", body = NotificationSettingsBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+        (status = 422, description = "Invalid email address", body = ErrorBody),
+        (status = 503, description = "Email notifications are turned off on this server", body = ErrorBody),
+    ),
+    security(("token" = ["settings:write"]))
+)]
+async fn update_notification_settings(
+    State(database): State<Database>,
+    State(mailer): State<Mailer>,
+    user: ApiUser,
+    Json(body): Json<NotificationSettingsBody>,
+) -> Result<Json<NotificationSettingsBody>, ApiError> {
+    user.require(ApiScope::SettingsWrite)?;
+
+    if !mailer.is_enabled() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "email notifications are turned off on this server",
+        ));
+    }
+
+    let email = body.email.trim();
+    if email.parse::<lettre::Address>().is_err() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("{email:?} is not a valid email address"),
+        ));
+    }
+
+    let mut settings = match database.get_notification_settings(user.account.id).await? {
+        Some(settings) => settings,
+        None => NotificationSettings::new(user.account.id, email.to_string()),
+    };
+
+    settings.change_email(email.to_string());
+    settings.backup_failures = body.backup_failures;
+    settings.authorization_revoked = body.authorization_revoked;
+    settings.weekly_summary = body.weekly_summary;
+    settings.unavailable_tracks = body.unavailable_tracks;
+
+    let settings = notifications::save_settings(&database, &mailer, settings).await?;
+
+    Ok(Json(NotificationSettingsBody::from_settings(settings)))
+}
+
+/// Stop all email notifications
+#[utoipa::path(
+    delete,
+    path = "/api/v1/settings/notifications",
+    tag = "settings",
+    responses(
+        (status = 204, description = "Notifications are turned off"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "The token is missing the scope", body = ErrorBody),
+    ),
+    security(("token" = ["settings:write"]))
+)]
+async fn delete_notification_settings(
+    State(database): State<Database>,
+    user: ApiUser,
+) -> Result<StatusCode, ApiError> {
+    user.require(ApiScope::SettingsWrite)?;
+
+    database
+        .delete_notification_settings(user.account.id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+
+    /// Clients are generated from the committed document, run `just openapi`
+    /// to update it after changing the api
+    #[test]
+    fn openapi_document_is_up_to_date() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+        let generated = ApiDoc::openapi()
+            .to_pretty_json()
+            .expect("openapi document should serialize")
+            + "\n";
+
+        if env::var_os("UPDATE_OPENAPI").is_some() {
+            fs::write(&path, generated).expect("openapi document should be written");
+            return;
+        }
+
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "openapi.json is out of date with the api handlers, run `just openapi` and commit the result"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use sea_orm::prelude::Uuid;
+use secrecy::ExposeSecret;
+use time::OffsetDateTime;
+
+use crate::{
+    api_tokens::{generate_token, hash_token, ApiScope, ApiToken, MAX_API_TOKENS},
+    database::{id::ApiTokenId, Database},
+    pages::{self, ClientError, InternalServerError},
+};
+
+use super::{
+    authentication::User,
+    csrf::{self, CsrfForm},
+};
+
+/// Longest name a token can be given
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The form repeats the `scope` field once for every checked scope
+pub async fn create(
+    State(database): State<Database>,
+    user: User,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response, InternalServerError> {
+    let mut csrf = String::new();
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+
+    for (field, value) in fields {
+        match field.as_str() {
+            "csrf" => csrf = value,
+            "name" => name = value.trim().to_string(),
+            "scope" => match ApiScope::parse(&value) {
+                Some(scope) if !scopes.contains(&scope) => scopes.push(scope),
+                Some(_) => {}
+                None => {
+                    return Ok(
+                        ClientError::new(format!("{value:?} is not a known scope")).into_response()
+                    )
+                }
+            },
+            _ => {}
+        }
+    }
+
+    if let Err(mismatch) = csrf::verify(&user.session, &csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Ok(ClientError::new(format!(
+            "the name must be between 1 and {MAX_NAME_LENGTH} characters"
+        ))
+        .into_response());
+    }
+
+    if scopes.is_empty() {
+        return Ok(
+            ClientError::new(String::from("choose at least one scope for the token"))
+                .into_response(),
+        );
+    }
+
+    if database.list_api_tokens(user.account.id).await?.len() >= MAX_API_TOKENS {
+        return Ok(
+            ClientError::new(format!("you can have at most {MAX_API_TOKENS} tokens"))
+                .into_response(),
+        );
+    }
+
+    let token = generate_token();
+
+    database
+        .create_api_token(
+            ApiToken {
+                id: ApiTokenId::generate(),
+                account: user.account.id,
+                name: name.clone(),
+                scopes,
+                created_at: OffsetDateTime::now_utc(),
+                last_used_at: None,
+            },
+            hash_token(token.expose_secret()),
+        )
+        .await?;
+
+    Ok(pages::api_token_created(name, token).into_response())
+}
+
+pub async fn delete(
+    State(database): State<Database>,
+    user: User,
+    Path(token): Path<Uuid>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    if !database
+        .delete_api_token(user.account.id, ApiTokenId::from_raw(token))
+        .await?
+    {
+        return Ok(ClientError::new(String::from("that token does not exist")).into_response());
+    }
+
+    Ok(Redirect::to("/account/tokens").into_response())
+}
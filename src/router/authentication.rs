@@ -1,70 +1,126 @@
 use axum::{
     async_trait,
-    extract::{FromRef, FromRequestParts, Query, State},
+    extract::{FromRef, FromRequestParts, State},
     http::request,
-    response::{Html, IntoResponse, Redirect, Response},
-    RequestPartsExt,
+    response::{IntoResponse, Redirect, Response},
+    Form, RequestPartsExt,
 };
 use axum_extra::either::Either3;
-use rspotify::prelude::OAuthClient;
+use rspotify::prelude::{Id, OAuthClient};
 use serde::Deserialize;
 use time::OffsetDateTime;
+use tracing::{debug, info};
 
 use crate::{
-    database::{id::AccountId, Database},
-    pages::InternalServerError,
+    database::{
+        id::{AccountId, UserSessionId},
+        Database,
+    },
+    pages::{self, ClientError, InternalServerError},
 };
 
-use self::{github::GithubAuthentication, spotify::SpotifyAuthentication};
+use self::{
+    github::{GithubAuthentication, GithubInstallation},
+    spotify::SpotifyAuthentication,
+};
 
-use super::session::{UserSession, UserSessionRejection};
+use super::{
+    csrf::{self, CsrfForm},
+    session::{self, UserSession, UserSessionRejection},
+};
 
 pub mod github;
 pub mod spotify;
+mod state;
 
 pub async fn logout(
     State(database): State<Database>,
-    session: Option<UserSession>,
+    user: Option<User>,
+    Form(form): Form<CsrfForm>,
 ) -> Result<Response, InternalServerError> {
-    if let Some(session) = session {
-        let session = database.logout_current_user(session).await?;
+    let Some(user) = user else {
+        // Whatever cookie is left is not a valid session anymore
+        return Ok((UserSession::remove(), Redirect::to("/")).into_response());
+    };
 
-        Ok((session, Redirect::to("/")).into_response())
-    } else {
-        Ok(Redirect::to("/").into_response())
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
     }
+
+    let session = database
+        .logout_current_user(UserSession {
+            id: UserSessionId::from_model(user.session),
+        })
+        .await?;
+
+    Ok((session, Redirect::to("/")).into_response())
 }
 
+/// Phrase that must be typed to delete an account, so that it is not done by
+/// accident
+pub const DECREE: &str = "I solemnly swear that I am deleting my account";
+
+/// Where users remove the access of the app to their spotify account, spotify
+/// has no api to revoke it for them
+pub const SPOTIFY_APPS_URL: &str = "https://www.spotify.com/account/apps/";
+
+/// How long a deleted account can still be restored by logging in again
+pub const DELETION_GRACE_PERIOD: time::Duration = time::Duration::days(7);
+
 #[derive(Debug, Deserialize)]
-pub struct DeleteQuery {
+pub struct DeleteForm {
+    csrf: String,
     decree: String,
+    #[serde(default)]
+    immediately: bool,
 }
 
-const DECREE: &str = "I solemnly swear that I am deleting my account";
-
 pub async fn delete(
     State(database): State<Database>,
-    user: Option<User>,
-    query: Option<Query<DeleteQuery>>,
+    State(reqwest): State<reqwest::Client>,
+    user: User,
+    Form(form): Form<DeleteForm>,
 ) -> Result<Response, InternalServerError> {
-    // FIXME: make this flow cleaner
-    if let Some(user) = user {
-        if let Some(query) = query {
-            if query.decree == DECREE {
-                let session = database.delete_current_user(user).await?;
-
-                Ok((session, Redirect::to("/")).into_response())
-            } else {
-                Ok(("Invalid decree, your account is not deleted").into_response())
-            }
-        } else {
-            Ok(Html(format!("<form action=\"/logout/delete\" method=\"get\"><label>Please type the following phrase: <pre>{DECREE}</pre> <input type=\"text\" name=\"decree\" /></label><button type=\"submit\">Delete my account</button><a href=\"/account\">Go back</a></form>")).into_response())
-        }
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    if form.decree.trim() != DECREE {
+        return Ok(ClientError::new(String::from(
+            "the phrase did not match, your account is not deleted",
+        ))
+        .into_response());
+    }
+
+    if form.immediately {
+        let session = database.delete_current_user(&user).await?;
+        user.account.revoke_upstream_grants(&reqwest).await;
+
+        Ok((session, pages::account_deleted(None)).into_response())
     } else {
-        Ok(Redirect::to("/").into_response())
+        let deletion_at = OffsetDateTime::now_utc() + DELETION_GRACE_PERIOD;
+        let session = database
+            .schedule_account_deletion(user.account.id, deletion_at)
+            .await?;
+
+        Ok((session, pages::account_deleted(Some(deletion_at))).into_response())
     }
 }
 
+pub async fn cancel_deletion(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    database.cancel_account_deletion(user.account.id).await?;
+
+    Ok(Redirect::to("/account").into_response())
+}
+
 #[derive(Debug)]
 pub struct User {
     pub session: entity::user_session::Model,
@@ -75,6 +131,9 @@ pub struct User {
 pub struct Account {
     pub id: AccountId,
     pub created_at: OffsetDateTime,
+    /// When the account is going to be deleted, logging in again and
+    /// cancelling keeps it
+    pub deletion_scheduled_at: Option<OffsetDateTime>,
 
     pub spotify: SpotifyAuthentication,
     pub github: Option<GithubAuthentication>,
@@ -86,6 +145,26 @@ impl Account {
         InternalServerError::wrap_in_current_span(self.spotify.as_client().current_user()).await
     }
 
+    /// Revoke what the account authorized once it has been deleted, every
+    /// step is logged for auditing and a failing step does not stop the rest
+    #[tracing::instrument(skip(self), fields(account.id = ?self.id))]
+    pub async fn revoke_upstream_grants(&self, reqwest: &reqwest::Client) {
+        if let Some(github) = &self.github {
+            github.revoke(reqwest).await;
+        }
+
+        info!(
+            spotify = self.spotify.user_id.id(),
+            "spotify grants can not be revoked by the app, left for the user to revoke"
+        );
+    }
+
+    /// Where backups are committed to, once both a github account is linked
+    /// and a repository is chosen
+    pub fn backup_installation(&self) -> Option<&GithubInstallation> {
+        self.github.as_ref()?.installation.as_ref()
+    }
+
     #[tracing::instrument(skip(self), fields(account.id = ?self.id))]
     pub async fn github_user(
         &self,
@@ -105,6 +184,7 @@ impl Account {
 impl<S> FromRequestParts<S> for User
 where
     Database: FromRef<S>,
+    reqwest::Client: FromRef<S>,
     S: Sync,
 {
     type Rejection = Either3<Redirect, InternalServerError, UserSessionRejection>;
@@ -122,7 +202,54 @@ where
                     .await
                     .map_err(Either3::E2)?;
 
-                if let Some(user) = user {
+                if let Some(mut user) = user {
+                    let now = OffsetDateTime::now_utc();
+
+                    if session::is_expired(&user.session, now) {
+                        debug!(session = ?user_session.id, "session expired");
+
+                        database
+                            .logout_current_user(user_session)
+                            .await
+                            .map_err(Either3::E2)?;
+
+                        return Err(Either3::E3(UserSessionRejection::Expired));
+                    }
+
+                    if now - user.session.last_seen > session::LAST_SEEN_INTERVAL {
+                        // Not worth failing the request over
+                        if database
+                            .touch_user_session(user_session.id, now)
+                            .await
+                            .is_ok()
+                        {
+                            user.session.last_seen = now;
+                        }
+                    }
+
+                    if user
+                        .account
+                        .github
+                        .as_ref()
+                        .is_some_and(GithubAuthentication::needs_refresh)
+                    {
+                        let reqwest = reqwest::Client::from_ref(state);
+
+                        // Pages that use the expired tokens fail on their own, the
+                        // rest of the account should stay usable
+                        match database
+                            .refresh_github_authentication(user.account.id, &reqwest)
+                            .await
+                        {
+                            Ok(github) => user.account.github = github,
+                            Err(error) => {
+                                tracing::warn!(?error, "failed to refresh github authentication");
+                            }
+                        }
+                    }
+
+                    sentry::configure_scope(|scope| scope.set_tag("account", user.account.id));
+
                     return Ok(user);
                 }
             }
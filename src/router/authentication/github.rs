@@ -1,34 +1,47 @@
 use axum::{
     extract::{Query, State},
-    response::Redirect,
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use octocrab::models::{
+    InstallationId, InstallationRepositories, Repository, RepositoryId, UserId,
 };
-use octocrab::models::UserId;
 use reqwest::header;
 use secrecy::{ExposeSecret, Secret, SecretString};
 use serde::Deserialize;
 use time::OffsetDateTime;
-use tracing::{debug, error_span, trace, warn, Instrument};
+use tracing::{debug, error_span, info, trace, warn, Instrument};
+use url::Url;
 
 use crate::{
-    database::{id::AccountId, GithubAccountAlreadyTakenError},
-    environment::GITHUB_ENVIRONMENT,
+    database::{
+        id::{AccountId, UserSessionId},
+        Database, GithubAccountAlreadyTakenError,
+    },
+    environment::{GITHUB_ENVIRONMENT, TOKEN_KEYRING},
     internal_server_error,
-    pages::InternalServerError,
-    router::AppState,
+    pages::{self, ClientError, InternalServerError},
+    router::{
+        csrf::{self, CsrfForm},
+        AppState,
+    },
 };
 
-use super::User;
+use super::{state, User};
 
 #[derive(Debug, Deserialize)]
 #[serde(untagged, deny_unknown_fields)]
 pub enum GithubAuthCodeResponse {
     Success {
         code: String,
+        state: String,
     },
     Failure {
         error: String,
         error_description: String,
         error_uri: String,
+        state: String,
     },
 }
 
@@ -39,6 +52,10 @@ enum GithubAccessTokenResponse {
         access_token: String,
         scope: String,
         token_type: String,
+        /// Only when the app has user token expiration enabled, in seconds
+        expires_in: Option<i64>,
+        refresh_token: Option<String>,
+        refresh_token_expires_in: Option<i64>,
     },
     Failure {
         error: String,
@@ -48,51 +65,75 @@ enum GithubAccessTokenResponse {
 }
 
 pub async fn logout(
-    State(AppState { database, .. }): State<AppState>,
+    State(AppState {
+        database, reqwest, ..
+    }): State<AppState>,
     user: Option<User>,
-) -> Result<Redirect, InternalServerError> {
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
     let user = match user {
         Some(user) => user,
         None => {
             warn!("user attempted to logout of github while logged out");
-            return Ok(Redirect::to("/account"));
+            return Ok(Redirect::to("/account").into_response());
         }
     };
 
-    database.remove_github_from_account(user).await?;
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    database.remove_github_from_account(&user).await?;
 
-    Ok(Redirect::to("/account"))
+    if let Some(github) = &user.account.github {
+        github.revoke(&reqwest).await;
+    }
+
+    Ok(Redirect::to("/account").into_response())
 }
 
 pub async fn login(
-    State(AppState { database, reqwest }): State<AppState>,
+    State(AppState {
+        database, reqwest, ..
+    }): State<AppState>,
     query: Option<Query<GithubAuthCodeResponse>>,
     user: Option<User>,
-) -> Result<Redirect, InternalServerError> {
+) -> Result<Response, InternalServerError> {
     let user = match user {
         Some(user) => user,
         None => {
             warn!("user attempted to add github while logged out");
-            return Ok(Redirect::to("/account"));
+            return Ok(Redirect::to("/account").into_response());
         }
     };
 
+    let session = UserSessionId::from_model(user.session.clone());
+
     let auth_code_response = match query {
         Some(Query(auth)) => auth,
         None => {
-            return Ok(Redirect::to(&format!(
-                "https://github.com/login/oauth/authorize?client_id={}&redirect_uri={}",
-                GITHUB_ENVIRONMENT.client_id, GITHUB_ENVIRONMENT.redirect_uri
-            )));
+            let state = state::generate();
+            database.set_oauth_state(session, &state).await?;
+
+            return Ok(Redirect::to(authorize_url(&state, false).as_str()).into_response());
         }
     };
 
+    // The flow is over either way, so the state is taken regardless of the outcome
+    let issued_state = database.take_oauth_state(session).await?;
+
     match auth_code_response {
         GithubAuthCodeResponse::Failure {
             error,
             error_description,
             error_uri,
+            state,
         } => {
+            // Do not report errors for callbacks this session did not ask for
+            if let Err(mismatch) = state::check(issued_state, &state) {
+                return Ok(mismatch.into_response());
+            }
+
             debug!(
                 ?error,
                 ?error_description,
@@ -107,107 +148,339 @@ pub async fn login(
                 error_uri,
             ))
         }
-        GithubAuthCodeResponse::Success { code } => {
+        GithubAuthCodeResponse::Success { code, state } => {
+            if let Err(mismatch) = state::check(issued_state, &state) {
+                return Ok(mismatch.into_response());
+            }
+
             trace!("succeeded github oauth");
 
-            // FIXME: mess, use octocrab?
-            let response = InternalServerError::wrap(
-                async {
-                    reqwest
-                        .get("https://github.com/login/oauth/access_token")
-                        .query(&[
-                            ("client_id", GITHUB_ENVIRONMENT.client_id.clone()),
-                            ("client_secret", GITHUB_ENVIRONMENT.client_secret.clone()),
-                            ("redirect_uri", GITHUB_ENVIRONMENT.redirect_uri.to_string()),
-                            ("code", code),
-                        ])
-                        .header(header::ACCEPT, "application/json")
-                        .header("X-GitHub-Api-Version", "2022-11-28")
-                        .send()
-                        .await?
-                        .error_for_status()
-                },
-                error_span!("exchanging authorization code").or_current(),
-            )
-            .await?;
+            let tokens = request_tokens(&reqwest, &[("code", &code)])
+                .instrument(error_span!("exchanging authorization code"))
+                .await?;
 
-            let token_json = InternalServerError::wrap(
-                response.text(),
-                error_span!("receiving github access_token response"),
-            )
-            .await?;
+            let auth = GithubAuthentication::create(tokens).await?;
 
-            let access_token = error_span!(
-                "deserializing github access_token response",
-                json = token_json
-            )
-            .in_scope(|| {
-                let deserialized_json: GithubAccessTokenResponse =
-                    serde_json::from_str(&token_json).map_err(InternalServerError::from_error)?;
+            let span = error_span!("logging in github account", github.id = auth.user_id.0);
 
-                match deserialized_json {
-                    GithubAccessTokenResponse::Success {
-                        access_token,
-                        scope,
-                        token_type,
-                    } => {
-                        if !scope.is_empty() {
-                            return Err(internal_server_error!(
-                                "github oauth scopes is not empty",
-                                scope,
-                            ));
-                        }
-                        if !token_type.eq_ignore_ascii_case("bearer") {
-                            return Err(internal_server_error!(
-                                "github oauth token type is not bearer",
-                                token_type,
-                            ));
-                        }
-
-                        Ok(Secret::new(access_token))
-                    }
-                    GithubAccessTokenResponse::Failure {
-                        error,
-                        error_description,
-                        error_uri,
-                    } => Err(internal_server_error!(
-                        "github endpoint returned error",
-                        error,
-                        error_description,
-                        error_uri,
-                    )),
+            // The state was issued by this server, so the transfer was requested by this user
+            if let Some(github_id) = transfer_target(&state) {
+                if auth.user_id != github_id {
+                    return Ok(ClientError::new(String::from(
+                        "the github account you authorized is not the one you asked to transfer. \
+                        Nothing was changed, make sure you are signed in to the right github account.",
+                    ))
+                    .into_response());
                 }
-            })?;
 
-            let auth = GithubAuthentication::create(access_token).await?;
+                database
+                    .transfer_github_to_account(user, auth)
+                    .instrument(span)
+                    .await?;
 
-            let span = error_span!("logging in github account", github.id = auth.user_id.0);
+                return Ok(Redirect::to("/account").into_response());
+            }
+
+            let github_id = auth.user_id;
+            let csrf = csrf::token(&user.session);
             match database
                 .associate_github_to_account(user, auth)
                 .instrument(span)
                 .await?
             {
-                Ok(()) => Ok(Redirect::to("/account")),
-                Err(GithubAccountAlreadyTakenError) => todo!("account already taken"),
+                Ok(()) => Ok(Redirect::to("/account").into_response()),
+                Err(GithubAccountAlreadyTakenError) => {
+                    debug!(
+                        github.id = github_id.0,
+                        "github account linked to another account"
+                    );
+
+                    Ok(pages::github_account_taken(github_id, &csrf))
+                }
+            }
+        }
+    }
+}
+
+/// Prefix of the `state` of flows that move a github account from another
+/// account, followed by the id of the github account and a random value
+const TRANSFER_STATE_PREFIX: &str = "transfer.";
+
+/// With `select_account`, github always shows the account picker instead of
+/// continuing with the github account that is currently signed in
+fn authorize_url(state: &str, select_account: bool) -> Url {
+    let mut url = Url::parse_with_params(
+        "https://github.com/login/oauth/authorize",
+        [
+            ("client_id", GITHUB_ENVIRONMENT.client_id.as_str()),
+            ("redirect_uri", &GITHUB_ENVIRONMENT.redirect_uri.to_string()),
+            ("state", state),
+        ],
+    )
+    .expect("authorization url should be valid");
+
+    if select_account {
+        url.query_pairs_mut()
+            .append_pair("prompt", "select_account");
+    }
+
+    url
+}
+
+/// Link a different github account, letting the user pick which one
+pub async fn switch(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Redirect, InternalServerError> {
+    let session = UserSessionId::from_model(user.session.clone());
+
+    let state = state::generate();
+    database.set_oauth_state(session, &state).await?;
+
+    Ok(Redirect::to(authorize_url(&state, true).as_str()))
+}
+
+fn transfer_target(state: &str) -> Option<UserId> {
+    let (github_id, _) = state.strip_prefix(TRANSFER_STATE_PREFIX)?.split_once('.')?;
+
+    github_id.parse().ok().map(UserId)
+}
+
+/// Tokens of a github user, from authorizing or refreshing an authorization
+struct GithubTokens {
+    access_token: SecretString,
+    expires_at: Option<OffsetDateTime>,
+    refresh_token: Option<SecretString>,
+    refresh_token_expires_at: Option<OffsetDateTime>,
+}
+
+/// Request new tokens with either an authorization code or a refresh token
+async fn request_tokens(
+    reqwest: &reqwest::Client,
+    grant: &[(&str, &str)],
+) -> Result<GithubTokens, InternalServerError> {
+    // FIXME: mess, use octocrab?
+    let response = InternalServerError::wrap(
+        async {
+            reqwest
+                .get("https://github.com/login/oauth/access_token")
+                .query(&[
+                    ("client_id", GITHUB_ENVIRONMENT.client_id.clone()),
+                    ("client_secret", GITHUB_ENVIRONMENT.client_secret.clone()),
+                    ("redirect_uri", GITHUB_ENVIRONMENT.redirect_uri.to_string()),
+                ])
+                .query(grant)
+                .header(header::ACCEPT, "application/json")
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .send()
+                .await?
+                .error_for_status()
+        },
+        error_span!("requesting github access token").or_current(),
+    )
+    .await?;
+
+    let token_json = InternalServerError::wrap(
+        response.text(),
+        error_span!("receiving github access_token response"),
+    )
+    .await?;
+
+    let requested_at = OffsetDateTime::now_utc();
+
+    error_span!(
+        "deserializing github access_token response",
+        json = token_json
+    )
+    .in_scope(|| {
+        let deserialized_json: GithubAccessTokenResponse =
+            serde_json::from_str(&token_json).map_err(InternalServerError::from_error)?;
+
+        match deserialized_json {
+            GithubAccessTokenResponse::Success {
+                access_token,
+                scope,
+                token_type,
+                expires_in,
+                refresh_token,
+                refresh_token_expires_in,
+            } => {
+                if !scope.is_empty() {
+                    return Err(internal_server_error!(
+                        "github oauth scopes is not empty",
+                        scope,
+                    ));
+                }
+                if !token_type.eq_ignore_ascii_case("bearer") {
+                    return Err(internal_server_error!(
+                        "github oauth token type is not bearer",
+                        token_type,
+                    ));
+                }
+
+                Ok(GithubTokens {
+                    access_token: Secret::new(access_token),
+                    expires_at: expires_in
+                        .map(|seconds| requested_at + time::Duration::seconds(seconds)),
+                    refresh_token: refresh_token.map(Secret::new),
+                    refresh_token_expires_at: refresh_token_expires_in
+                        .map(|seconds| requested_at + time::Duration::seconds(seconds)),
+                })
             }
+            GithubAccessTokenResponse::Failure {
+                error,
+                error_description,
+                error_uri,
+            } => Err(internal_server_error!(
+                "github endpoint returned error",
+                error,
+                error_description,
+                error_uri,
+            )),
         }
+    })
+}
+
+/// Tokens are refreshed this long before they expire, so that they do not
+/// expire in the middle of handling a request
+const REFRESH_MARGIN: time::Duration = time::Duration::minutes(5);
+
+#[derive(Debug, Deserialize)]
+pub struct TransferForm {
+    csrf: String,
+    github: u64,
+}
+
+/// Start authorizing the github account again, to prove that the user owns it
+/// before moving it away from the account it is linked to
+pub async fn transfer(
+    State(database): State<Database>,
+    user: User,
+    Form(TransferForm { csrf, github }): Form<TransferForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &csrf) {
+        return Ok(mismatch.into_response());
     }
+
+    let session = UserSessionId::from_model(user.session.clone());
+
+    let state = format!("{TRANSFER_STATE_PREFIX}{github}.{}", state::generate());
+    database.set_oauth_state(session, &state).await?;
+
+    // Picking the account again also makes github show which account it is
+    Ok(Redirect::to(authorize_url(&state, true).as_str()).into_response())
+}
+
+/// Send the user to github to install the app on the repository that
+/// backups should be committed to, github sends the user back to the setup
+/// url of the app, which is configured to be `/account/repository`
+pub async fn install(_user: User) -> Result<Redirect, InternalServerError> {
+    let app: GithubApp = InternalServerError::wrap(
+        GITHUB_ENVIRONMENT.client.get("/app", None::<&()>),
+        error_span!("fetching github app"),
+    )
+    .await?;
+
+    Ok(Redirect::to(&format!("{}/installations/new", app.html_url)))
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubApp {
+    html_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RepositoryForm {
+    csrf: String,
+    installation: u64,
+    repository: u64,
+}
+
+/// Commit backups to a repository the app is installed on
+pub async fn select_repository(
+    State(database): State<Database>,
+    user: User,
+    Form(RepositoryForm {
+        csrf,
+        installation,
+        repository,
+    }): Form<RepositoryForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    let Some(github) = &user.account.github else {
+        return Ok(Redirect::to("/account").into_response());
+    };
+
+    let installation = GithubInstallation {
+        id: InstallationId(installation),
+        repository: RepositoryId(repository),
+    };
+
+    // The form can not be trusted, only repositories the user has access to can be chosen
+    let accessible = github
+        .installed_repositories()
+        .await?
+        .iter()
+        .any(|installed| {
+            installed.installation == installation.id
+                && installed.repository.id == installation.repository
+        });
+    if !accessible {
+        return Ok(ClientError::new(String::from(
+            "the app is not installed on that repository, or you do not have access to it",
+        ))
+        .into_response());
+    }
+
+    database
+        .set_github_installation(user.account.id, installation)
+        .await?;
+
+    Ok(Redirect::to("/account").into_response())
+}
+
+/// The repository backups are committed to, and the installation of the
+/// github app that grants access to it
+#[derive(Debug, Clone, Copy)]
+pub struct GithubInstallation {
+    pub id: InstallationId,
+    pub repository: RepositoryId,
+}
+
+/// A repository that the github app is installed on
+#[derive(Debug)]
+pub struct InstalledRepository {
+    pub installation: InstallationId,
+    pub repository: Repository,
 }
 
 #[derive(Debug)]
 pub struct GithubAuthentication {
     access_token: SecretString,
+    refresh_token: Option<SecretString>,
 
     pub user_id: UserId,
     pub created_at: OffsetDateTime,
+    /// Access tokens do not expire if expiration is disabled for the app
+    pub expires_at: Option<OffsetDateTime>,
+    pub refresh_token_expires_at: Option<OffsetDateTime>,
+    /// Not chosen yet when the github account has just been linked
+    pub installation: Option<GithubInstallation>,
 }
 
 impl GithubAuthentication {
-    async fn create(access_token: Secret<String>) -> Result<Self, InternalServerError> {
+    async fn create(tokens: GithubTokens) -> Result<Self, InternalServerError> {
         let mut auth = Self {
-            access_token,
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
             user_id: UserId(u64::MAX), // Populate later
             created_at: OffsetDateTime::now_utc(),
+            expires_at: tokens.expires_at,
+            refresh_token_expires_at: tokens.refresh_token_expires_at,
+            installation: None,
         };
 
         let current_user = InternalServerError::wrap(
@@ -221,6 +494,200 @@ impl GithubAuthentication {
         Ok(auth)
     }
 
+    /// Whether the access token has expired, or is about to, and can be refreshed
+    pub fn needs_refresh(&self) -> bool {
+        self.refresh_token.is_some()
+            && self
+                .expires_at
+                .is_some_and(|expires_at| expires_at - OffsetDateTime::now_utc() < REFRESH_MARGIN)
+    }
+
+    /// Exchange the refresh token for new tokens, github invalidates the
+    /// refresh token that was used, so the new tokens must be saved
+    #[tracing::instrument(skip_all, fields(github = self.user_id.0))]
+    pub async fn refresh(&self, reqwest: &reqwest::Client) -> Result<Self, InternalServerError> {
+        let Some(refresh_token) = &self.refresh_token else {
+            return Err(internal_server_error!(
+                "github authentication has no refresh token"
+            ));
+        };
+
+        let tokens = request_tokens(
+            reqwest,
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token.expose_secret()),
+            ],
+        )
+        .await?;
+
+        Ok(Self {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            user_id: self.user_id,
+            created_at: self.created_at,
+            expires_at: tokens.expires_at,
+            refresh_token_expires_at: tokens.refresh_token_expires_at,
+            installation: self.installation,
+        })
+    }
+
+    /// Revoke the authorization and remove a personal installation of the
+    /// app, once the github account is unlinked. Failures are only logged,
+    /// the user can still revoke the app from their github settings
+    #[tracing::instrument(skip_all, fields(github = self.user_id.0))]
+    pub async fn revoke(&self, reqwest: &reqwest::Client) {
+        match self.revoke_authorization(reqwest).await {
+            Ok(()) => info!("revoked github app authorization"),
+            Err(_) => warn!("failed to revoke github app authorization"),
+        }
+
+        match self.remove_personal_installation().await {
+            Ok(true) => info!("removed github app installation"),
+            Ok(false) => info!("no github app installation to remove"),
+            Err(_) => warn!("failed to remove github app installation"),
+        }
+    }
+
+    /// An access token that github still accepts, refreshing the stored one
+    /// if it has expired. The refreshed tokens are not saved, so this is only
+    /// for tokens that are about to be discarded
+    async fn unexpired_access_token(
+        &self,
+        reqwest: &reqwest::Client,
+    ) -> Result<SecretString, InternalServerError> {
+        let now = OffsetDateTime::now_utc();
+
+        if self
+            .expires_at
+            .filter(|&expires_at| expires_at <= now)
+            .is_none()
+        {
+            return Ok(self.access_token.clone());
+        }
+
+        if self
+            .refresh_token_expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+        {
+            return Err(internal_server_error!(
+                "github access and refresh tokens have both expired"
+            ));
+        }
+
+        Ok(self.refresh(reqwest).await?.access_token)
+    }
+
+    /// Delete the authorization of the app for this github user, which
+    /// invalidates every token the app holds for them
+    #[tracing::instrument(skip_all, fields(github = self.user_id.0))]
+    pub async fn revoke_authorization(
+        &self,
+        reqwest: &reqwest::Client,
+    ) -> Result<(), InternalServerError> {
+        // Github answers expired tokens the same way as revoked ones, so the
+        // answer is only meaningful for a token that has not expired
+        let access_token = self.unexpired_access_token(reqwest).await?;
+
+        let client = octocrab::Octocrab::builder()
+            .basic_auth(
+                GITHUB_ENVIRONMENT.client_id.clone(),
+                GITHUB_ENVIRONMENT.client_secret.clone(),
+            )
+            .build()
+            .map_err(InternalServerError::from_error)?;
+
+        let response = InternalServerError::wrap(
+            client._delete(
+                format!("/applications/{}/grant", GITHUB_ENVIRONMENT.client_id),
+                Some(&serde_json::json!({
+                    "access_token": access_token.expose_secret(),
+                })),
+            ),
+            error_span!("deleting github app authorization"),
+        )
+        .await?;
+
+        // The unexpired token is rejected once the user has revoked the
+        // authorization themselves
+        if response.status() == StatusCode::NOT_FOUND
+            || response.status() == StatusCode::UNPROCESSABLE_ENTITY
+        {
+            debug!("github app authorization was already revoked");
+
+            return Ok(());
+        }
+
+        InternalServerError::wrap(
+            octocrab::map_github_error(response),
+            error_span!("deleting github app authorization"),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the installation of the app that backups were committed
+    /// through, but only when it is installed on this user's own github
+    /// account, installations on organizations are left to their members.
+    /// Returns whether the installation was removed
+    #[tracing::instrument(skip_all, fields(github = self.user_id.0))]
+    pub async fn remove_personal_installation(&self) -> Result<bool, InternalServerError> {
+        let Some(installation) = self.installation else {
+            return Ok(false);
+        };
+
+        let details = GITHUB_ENVIRONMENT
+            .client
+            .apps()
+            .installation(installation.id)
+            .instrument(error_span!(
+                "fetching github app installation",
+                installation = installation.id.0
+            ))
+            .await;
+
+        let details = match details {
+            Ok(details) => details,
+            Err(octocrab::Error::GitHub { source, .. }) if source.message == "Not Found" => {
+                debug!("github app installation was already removed");
+
+                return Ok(false);
+            }
+            Err(error) => return Err(InternalServerError::from_error(error)),
+        };
+
+        if details.account.id != self.user_id {
+            debug!(
+                owner = details.account.login,
+                "github app is installed on an organization, keeping it"
+            );
+
+            return Ok(false);
+        }
+
+        InternalServerError::wrap(
+            async {
+                let response = GITHUB_ENVIRONMENT
+                    .client
+                    ._delete(
+                        format!("/app/installations/{}", installation.id),
+                        None::<&()>,
+                    )
+                    .await?;
+
+                octocrab::map_github_error(response).await
+            },
+            error_span!(
+                "removing github app installation",
+                installation = installation.id.0
+            ),
+        )
+        .await?;
+
+        Ok(true)
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn as_client(&self) -> Result<octocrab::Octocrab, InternalServerError> {
         octocrab::OctocrabBuilder::new()
@@ -233,25 +700,108 @@ impl GithubAuthentication {
             .map_err(InternalServerError::from_error)
     }
 
+    /// Repositories of every installation of the github app that this github
+    /// user has access to
+    #[tracing::instrument(skip(self), fields(github = self.user_id.0))]
+    pub async fn installed_repositories(
+        &self,
+    ) -> Result<Vec<InstalledRepository>, InternalServerError> {
+        let client = self.as_client()?;
+
+        let installations = InternalServerError::wrap(
+            client
+                .current()
+                .list_app_installations_accessible_to_user()
+                .per_page(100u8)
+                .send(),
+            error_span!("listing installations accessible to user"),
+        )
+        .await?;
+
+        let mut repositories = Vec::new();
+        for installation in installations.items {
+            let installed: InstallationRepositories = InternalServerError::wrap(
+                client.get(
+                    format!("/user/installations/{}/repositories", installation.id),
+                    Some(&[("per_page", 100)]),
+                ),
+                error_span!(
+                    "listing repositories of installation",
+                    installation = installation.id.0
+                ),
+            )
+            .await?;
+
+            repositories.extend(installed.repositories.into_iter().map(|repository| {
+                InstalledRepository {
+                    installation: installation.id,
+                    repository,
+                }
+            }));
+        }
+
+        Ok(repositories)
+    }
+
+    /// Tokens are encrypted with a new data key every time they are saved
     pub fn into_model(self, account: AccountId) -> entity::github_auth::Model {
+        let envelope = TOKEN_KEYRING.new_envelope();
+
         entity::github_auth::Model {
             account: account.into_uuid(),
             user_id: self.user_id.to_string(),
-            access_token: self.access_token.expose_secret().clone(),
+            access_token: envelope.encrypt("access_token", self.access_token.expose_secret()),
             created_at: self.created_at,
+            installation_id: self
+                .installation
+                .map(|installation| installation.id.0 as i64),
+            repository_id: self
+                .installation
+                .map(|installation| installation.repository.0 as i64),
+            expires_at: self.expires_at,
+            refresh_token: self.refresh_token.map(|refresh_token| {
+                envelope.encrypt("refresh_token", refresh_token.expose_secret())
+            }),
+            refresh_token_expires_at: self.refresh_token_expires_at,
+            token_key_id: envelope.key_id,
+            token_data_key: envelope.wrapped_key,
         }
     }
 
-    pub fn from_model(model: entity::github_auth::Model) -> Self {
-        Self {
-            access_token: model.access_token.into(),
+    /// Fails if the master key of the row is missing from the keyring, or the
+    /// stored tokens do not decrypt, the error is reported when it is created
+    pub fn from_model(model: entity::github_auth::Model) -> Result<Self, InternalServerError> {
+        let envelope = TOKEN_KEYRING
+            .open_envelope(&model.token_key_id, &model.token_data_key)
+            .map_err(InternalServerError::from_error)?;
+        let decrypt = |field, ciphertext: &str| {
+            envelope
+                .decrypt(field, ciphertext)
+                .map(SecretString::new)
+                .map_err(InternalServerError::from_error)
+        };
+
+        Ok(Self {
+            access_token: decrypt("access_token", &model.access_token)?,
+            refresh_token: model
+                .refresh_token
+                .map(|refresh_token| decrypt("refresh_token", &refresh_token))
+                .transpose()?,
             user_id: UserId(
                 model
                     .user_id
                     .parse()
-                    .expect("user id should never be a non-integer"),
+                    .map_err(InternalServerError::from_error)?,
             ),
             created_at: model.created_at,
-        }
+            expires_at: model.expires_at,
+            refresh_token_expires_at: model.refresh_token_expires_at,
+            installation: model.installation_id.zip(model.repository_id).map(
+                |(installation, repository)| GithubInstallation {
+                    id: InstallationId(installation as u64),
+                    repository: RepositoryId(repository as u64),
+                },
+            ),
+        })
     }
 }
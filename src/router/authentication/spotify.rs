@@ -1,10 +1,10 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 use axum::{
     extract::{Query, State},
-    response::Redirect,
+    response::{IntoResponse, Redirect, Response},
 };
-use axum_extra::either::Either;
+use axum_extra::extract::SignedCookieJar;
 use once_cell::sync::Lazy;
 use rspotify::{
     model::{PrivateUser, UserId},
@@ -17,12 +17,24 @@ use time::OffsetDateTime;
 use tracing::{debug, error_span, trace};
 
 use crate::{
-    environment::SPOTIFY_ENVIRONMENT,
+    database::SpotifyAccountAlreadyTakenError,
+    environment::{SPOTIFY_ENVIRONMENT, TOKEN_KEYRING},
     internal_server_error,
-    pages::InternalServerError,
-    router::{session::UserSession, AppState},
+    pages::{ClientError, InternalServerError},
+    router::{
+        session::{SessionClient, UserSession},
+        AppState,
+    },
 };
 
+use super::{state, User};
+
+const STATE_COOKIE: &str = "spotify-oauth-state";
+
+/// Prefix of the `state` of flows started to link a different spotify account
+/// to the account that is logged in, instead of logging in
+const SWITCH_STATE_PREFIX: &str = "switch.";
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum SpotifyAuthCodeResponse {
@@ -33,32 +45,76 @@ pub enum SpotifyAuthCodeResponse {
 static REQUIRED_SCOPES: Lazy<HashSet<String>> =
     Lazy::new(|| scopes!("playlist-read-private", "user-library-read"));
 
+fn authorization_client(state: Option<String>) -> AuthCodeSpotify {
+    let mut oauth = rspotify::OAuth {
+        redirect_uri: SPOTIFY_ENVIRONMENT.redirect_uri.to_string(),
+        scopes: REQUIRED_SCOPES.clone(),
+        ..Default::default()
+    };
+    if let Some(state) = state {
+        oauth.state = state;
+    }
+
+    // FIXME: unify?
+    AuthCodeSpotify::new(SPOTIFY_ENVIRONMENT.credentials.clone(), oauth)
+}
+
+fn redirect_to_authorization(
+    jar: SignedCookieJar,
+    auth: &AuthCodeSpotify,
+    show_dialog: bool,
+) -> Response {
+    let auth_url = auth
+        .get_authorize_url(show_dialog)
+        .expect("authorization url should be valid");
+
+    let jar = state::issue(jar, STATE_COOKIE, &auth.oauth.state);
+
+    (jar, Redirect::to(&auth_url)).into_response()
+}
+
+/// Always show the spotify authorization dialog, so that the user can pick
+/// which spotify account is linked to the account that is logged in
+pub async fn switch(user: Option<User>, jar: SignedCookieJar) -> Response {
+    if user.is_none() {
+        return Redirect::to("/login/spotify").into_response();
+    }
+
+    let auth = authorization_client(Some(format!("{SWITCH_STATE_PREFIX}{}", state::generate())));
+
+    redirect_to_authorization(jar, &auth, true)
+}
+
 pub async fn login(
     State(AppState { database, .. }): State<AppState>,
     user_session: Option<UserSession>,
+    client: SessionClient,
+    current_user: Option<User>,
+    jar: SignedCookieJar,
     query: Option<Query<SpotifyAuthCodeResponse>>,
-) -> Result<Either<(UserSession, Redirect), Redirect>, InternalServerError> {
-    // FIXME: unify?
-    let auth = AuthCodeSpotify::new(
-        SPOTIFY_ENVIRONMENT.credentials.clone(),
-        rspotify::OAuth {
-            redirect_uri: SPOTIFY_ENVIRONMENT.redirect_uri.to_string(),
-            scopes: REQUIRED_SCOPES.clone(),
-            ..Default::default()
-        },
-    );
+) -> Result<Response, InternalServerError> {
+    let auth = authorization_client(None);
 
     if let Some(Query(response)) = query {
         match response {
             SpotifyAuthCodeResponse::Failure { error, state } => {
                 debug!(?error, "failed spotify oauth");
 
-                Err(internal_server_error!(
-                    "spotify authentication did not succeed",
-                    error
-                ))
+                // The flow is over either way, do not leave the state around
+                let (jar, _) = state::verify(jar, STATE_COOKIE, &state);
+
+                Ok((
+                    jar,
+                    internal_server_error!("spotify authentication did not succeed", error),
+                )
+                    .into_response())
             }
             SpotifyAuthCodeResponse::Success { code, state } => {
+                let (jar, verified) = state::verify(jar, STATE_COOKIE, &state);
+                if let Err(mismatch) = verified {
+                    return Ok((jar, mismatch).into_response());
+                }
+
                 trace!("succeeded spotify oauth");
 
                 InternalServerError::wrap(
@@ -91,22 +147,41 @@ pub async fn login(
                 )
                 .await?;
 
+                let spotify_auth = SpotifyAuthentication::create(token, user);
+
+                // The state was issued to this browser, so the switch was requested by this user
+                let switching = state.starts_with(SWITCH_STATE_PREFIX);
+                if let Some(current_user) = current_user.filter(|_| switching) {
+                    return match database
+                        .link_spotify_to_account(current_user, spotify_auth)
+                        .await?
+                    {
+                        Ok(()) => Ok((jar, Redirect::to("/account")).into_response()),
+                        Err(SpotifyAccountAlreadyTakenError) => Ok((
+                            jar,
+                            ClientError::new(String::from(
+                                "the spotify account you authorized already has its own spotify backup account. \
+                                Nothing was changed, log out and log in with that spotify account to use it.",
+                            )),
+                        )
+                            .into_response()),
+                    };
+                }
+
                 let new_session = database
-                    .login_user(user_session, SpotifyAuthentication::create(token, user))
+                    .login_user(user_session, client, spotify_auth)
                     .await?;
 
-                Ok(Either::E1((
+                Ok((
+                    jar,
                     UserSession { id: new_session },
                     Redirect::to("/account"),
-                )))
+                )
+                    .into_response())
             }
         }
     } else {
-        let auth_url = auth
-            .get_authorize_url(false)
-            .expect("authorization url should be valid");
-
-        Ok(Either::E2(Redirect::to(&auth_url)))
+        Ok(redirect_to_authorization(jar, &auth, false))
     }
 }
 
@@ -167,25 +242,42 @@ impl SpotifyAuthentication {
         client
     }
 
+    /// Tokens are encrypted with a new data key every time they are saved
     pub fn into_model(self) -> entity::spotify_auth::Model {
+        let envelope = TOKEN_KEYRING.new_envelope();
+
         entity::spotify_auth::Model {
             user_id: self.user_id.to_string(),
-            access_token: self.access_token.expose_secret().clone(),
+            access_token: envelope.encrypt("access_token", self.access_token.expose_secret()),
             expires_at: self.expires_at,
-            refresh_token: self.refresh_token.expose_secret().clone(),
+            refresh_token: envelope.encrypt("refresh_token", self.refresh_token.expose_secret()),
             created_at: self.created_at,
+            token_key_id: envelope.key_id,
+            token_data_key: envelope.wrapped_key,
         }
     }
 
-    pub fn from_model(model: entity::spotify_auth::Model) -> Self {
-        Self {
-            access_token: SecretString::new(model.access_token),
-            refresh_token: SecretString::new(model.refresh_token),
+    /// Fails if the master key of the row is missing from the keyring, or the
+    /// stored tokens do not decrypt, the error is reported when it is created
+    pub fn from_model(model: entity::spotify_auth::Model) -> Result<Self, InternalServerError> {
+        let envelope = TOKEN_KEYRING
+            .open_envelope(&model.token_key_id, &model.token_data_key)
+            .map_err(InternalServerError::from_error)?;
+        let decrypt = |field, ciphertext: &str| {
+            envelope
+                .decrypt(field, ciphertext)
+                .map(SecretString::new)
+                .map_err(InternalServerError::from_error)
+        };
+
+        Ok(Self {
+            access_token: decrypt("access_token", &model.access_token)?,
+            refresh_token: decrypt("refresh_token", &model.refresh_token)?,
             user_id: UserId::from_uri(&model.user_id)
-                .expect("user id should be valid")
+                .map_err(InternalServerError::from_error)?
                 .into_static(),
             expires_at: model.expires_at,
             created_at: model.created_at,
-        }
+        })
     }
 }
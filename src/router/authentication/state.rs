@@ -0,0 +1,163 @@
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    SignedCookieJar,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::pages::ClientError;
+
+/// How long a user has to complete an OAuth flow after it was started
+pub const MAX_AGE: time::Duration = time::Duration::minutes(10);
+
+/// Only sent back to the OAuth callbacks
+const COOKIE_PATH: &str = "/login";
+
+/// Bind the `state` of an OAuth flow to the browser that starts it, with a
+/// signed cookie that also records when the flow was started
+pub fn issue(jar: SignedCookieJar, name: &'static str, state: &str) -> SignedCookieJar {
+    let issued_at = OffsetDateTime::now_utc().unix_timestamp();
+
+    jar.add(
+        Cookie::build(name, format!("{issued_at}.{state}"))
+            .path(COOKIE_PATH)
+            // Lax, so that the cookie is sent on the redirect back from the provider
+            .same_site(SameSite::Lax)
+            .secure(true)
+            .http_only(true)
+            .max_age(MAX_AGE)
+            .finish(),
+    )
+}
+
+/// Check the `state` returned to the callback against the one issued to this
+/// browser, the cookie is removed either way so every state is single use
+pub fn verify(
+    jar: SignedCookieJar,
+    name: &'static str,
+    received: &str,
+) -> (SignedCookieJar, Result<(), StateMismatch>) {
+    let issued = jar.get(name).and_then(|cookie| {
+        let (issued_at, state) = cookie.value().split_once('.')?;
+        let issued_at = OffsetDateTime::from_unix_timestamp(issued_at.parse().ok()?).ok()?;
+
+        Some((state.to_string(), issued_at))
+    });
+
+    let result = check(issued, received);
+    let jar = jar.remove(Cookie::build(name, "").path(COOKIE_PATH).finish());
+
+    (jar, result)
+}
+
+/// Compare the `state` that was issued when the flow started, and when, to
+/// the `state` returned to the callback
+pub fn check(
+    issued: Option<(String, OffsetDateTime)>,
+    received: &str,
+) -> Result<(), StateMismatch> {
+    let result = match issued {
+        None => Err(StateMismatch::Missing),
+        Some((_, issued_at)) if OffsetDateTime::now_utc() - issued_at > MAX_AGE => {
+            Err(StateMismatch::Expired)
+        }
+        Some((state, _)) if state != received => Err(StateMismatch::Different),
+        Some(_) => Ok(()),
+    };
+
+    if let Err(mismatch) = &result {
+        debug!(?mismatch, "oauth state did not match");
+    }
+
+    result
+}
+
+/// A random value that is infeasible to guess
+pub fn generate() -> String {
+    rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum StateMismatch {
+    /// The flow was not started from this browser, or cookies are disabled
+    Missing,
+    Expired,
+    Different,
+}
+
+impl IntoResponse for StateMismatch {
+    fn into_response(self) -> Response {
+        let reason = match self {
+            StateMismatch::Missing => "the login was not started from this browser",
+            StateMismatch::Expired => "the login took longer than 10 minutes",
+            StateMismatch::Different => "the login was started from another tab or browser",
+        };
+
+        ClientError::new(format!(
+            "the login could not be verified, because {reason}. \
+            Nothing was changed, please try logging in again."
+        ))
+        .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_extra::extract::{cookie::Key, SignedCookieJar};
+    use time::OffsetDateTime;
+
+    use super::{check, issue, verify, StateMismatch, MAX_AGE};
+
+    const COOKIE: &str = "test-state";
+
+    #[test]
+    fn accepts_issued_state() {
+        let jar = issue(SignedCookieJar::new(Key::generate()), COOKIE, "state");
+
+        let (_, result) = verify(jar, COOKIE, "state");
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_cookie() {
+        let (_, result) = verify(SignedCookieJar::new(Key::generate()), COOKIE, "state");
+
+        assert!(matches!(result, Err(StateMismatch::Missing)));
+    }
+
+    #[test]
+    fn rejects_mismatched_state() {
+        let jar = issue(SignedCookieJar::new(Key::generate()), COOKIE, "state");
+
+        let (_, result) = verify(jar, COOKIE, "other");
+
+        assert!(matches!(result, Err(StateMismatch::Different)));
+    }
+
+    #[test]
+    fn rejects_expired_state() {
+        let issued_at = OffsetDateTime::now_utc() - MAX_AGE - time::Duration::seconds(1);
+
+        let result = check(Some((String::from("state"), issued_at)), "state");
+
+        assert!(matches!(result, Err(StateMismatch::Expired)));
+    }
+
+    #[test]
+    fn rejects_replayed_state() {
+        let jar = issue(SignedCookieJar::new(Key::generate()), COOKIE, "state");
+
+        let (jar, first) = verify(jar, COOKIE, "state");
+        let (_, replayed) = verify(jar, COOKIE, "state");
+
+        assert!(first.is_ok());
+        assert!(matches!(replayed, Err(StateMismatch::Missing)));
+    }
+}
@@ -0,0 +1,108 @@
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::Key;
+use hmac::{Hmac, Mac};
+use sea_orm::prelude::Uuid;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{environment::HTTP_ENVIRONMENT, pages::ClientError};
+
+/// A form that only carries the token
+#[derive(Debug, Deserialize)]
+pub struct CsrfForm {
+    pub csrf: String,
+}
+
+fn mac(key: &Key, session: Uuid) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.signing())
+        .expect("hmac should accept keys of any length");
+    mac.update(b"csrf:");
+    mac.update(session.as_bytes());
+
+    mac
+}
+
+/// Token that forms changing state must include, it is bound to the session
+/// so other sites can not submit those forms for a logged in user
+pub fn token(session: &entity::user_session::Model) -> String {
+    token_with_key(&HTTP_ENVIRONMENT.cookie_key, session.id)
+}
+
+pub fn verify(session: &entity::user_session::Model, token: &str) -> Result<(), CsrfMismatch> {
+    verify_with_key(&HTTP_ENVIRONMENT.cookie_key, session.id, token)
+}
+
+fn token_with_key(key: &Key, session: Uuid) -> String {
+    hex::encode(mac(key, session).finalize().into_bytes())
+}
+
+fn verify_with_key(key: &Key, session: Uuid, token: &str) -> Result<(), CsrfMismatch> {
+    let token = hex::decode(token).map_err(|_| CsrfMismatch)?;
+
+    mac(key, session).verify_slice(&token).map_err(|_| {
+        tracing::debug!("csrf token did not match");
+
+        CsrfMismatch
+    })
+}
+
+#[derive(Debug)]
+pub struct CsrfMismatch;
+
+impl IntoResponse for CsrfMismatch {
+    fn into_response(self) -> Response {
+        ClientError::new(String::from(
+            "this form was not submitted from spotify backup, or your session changed since it was loaded. \
+            Nothing was changed, go back, reload the page and try again.",
+        ))
+        .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_extra::extract::cookie::Key;
+    use sea_orm::prelude::Uuid;
+
+    use super::{token_with_key, verify_with_key};
+
+    #[test]
+    fn accepts_token_of_session() {
+        let key = Key::generate();
+        let session = Uuid::new_v4();
+
+        let token = token_with_key(&key, session);
+
+        assert!(verify_with_key(&key, session, &token).is_ok());
+    }
+
+    #[test]
+    fn rejects_token_of_other_session() {
+        let key = Key::generate();
+        let token = token_with_key(&key, Uuid::new_v4());
+
+        assert!(verify_with_key(&key, Uuid::new_v4(), &token).is_err());
+    }
+
+    #[test]
+    fn rejects_token_signed_with_other_key() {
+        let session = Uuid::new_v4();
+        let token = token_with_key(&Key::generate(), session);
+
+        assert!(verify_with_key(&Key::generate(), session, &token).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        let key = Key::generate();
+        let session = Uuid::new_v4();
+        let token = token_with_key(&key, session);
+
+        for malformed in ["", "not hex", &token[..token.len() - 2]] {
+            assert!(
+                verify_with_key(&key, session, malformed).is_err(),
+                "{malformed:?} should be rejected"
+            );
+        }
+    }
+}
@@ -20,10 +20,16 @@ pub fn internal_server_error_panic(info: CaughtPanic) -> Response {
         None => &"Unknown" as &dyn Display,
     };
 
+    let request_id = info.request_id().unwrap_or("None");
+
     if let Some(panic) = info.payload_str() {
-        error!(%panic, %location, "service panicked");
+        error!(%panic, %location, %request_id, "service panicked");
     } else {
-        error!("service panicked but panic info was not a &str or String");
+        error!(
+            %location,
+            %request_id,
+            "service panicked but panic info was not a &str or String"
+        );
     }
 
     pages::panic_error(info).into_response()
@@ -0,0 +1,315 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    backup::{github::BackupRepository, snapshot::Snapshot, BackupOutcome},
+    database::{id::UserSessionId, AccountRows, Database},
+    internal_server_error,
+    pages::InternalServerError,
+};
+
+use super::authentication::User;
+
+/// Everything stored about an account, except for secrets like tokens
+#[derive(Debug, Serialize)]
+pub struct DataExport {
+    #[serde(with = "time::serde::rfc3339")]
+    exported_at: OffsetDateTime,
+    account: AccountExport,
+    spotify: Option<SpotifyExport>,
+    github: Option<GithubExport>,
+    sessions: Vec<SessionExport>,
+    notification_settings: Option<NotificationSettingsExport>,
+    backup_runs: Vec<BackupRunExport>,
+    webhooks: Vec<WebhookExport>,
+    webhook_deliveries: Vec<WebhookDeliveryExport>,
+    api_tokens: Vec<ApiTokenExport>,
+    /// The library as of the most recent backup that changed it
+    latest_snapshot: Option<SnapshotExport>,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountExport {
+    id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    deletion_scheduled_at: Option<OffsetDateTime>,
+    feed_enabled: bool,
+    webhook_secret_set: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SpotifyExport {
+    user_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    access_token_expires_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GithubExport {
+    user_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    installation_id: Option<i64>,
+    repository_id: Option<i64>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    access_token_expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    refresh_token_expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionExport {
+    /// The session id is the secret of the session cookie, so only the handle
+    /// shown on the sessions page is exported
+    handle: String,
+    current: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen: OffsetDateTime,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NotificationSettingsExport {
+    email: String,
+    backup_failures: bool,
+    authorization_revoked: bool,
+    weekly_summary: bool,
+    unavailable_tracks: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_weekly_summary: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct BackupRunExport {
+    id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    finished_at: OffsetDateTime,
+    status: String,
+    commit: Option<String>,
+    summary: Option<String>,
+    diff: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookExport {
+    id: String,
+    url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookDeliveryExport {
+    id: String,
+    webhook: String,
+    backup_run: String,
+    attempt: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    attempted_at: OffsetDateTime,
+    status_code: Option<i16>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiTokenExport {
+    id: String,
+    name: String,
+    scopes: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotExport {
+    commit: String,
+    snapshot: Snapshot,
+}
+
+impl DataExport {
+    fn from_rows(rows: AccountRows, current_session: UserSessionId) -> Self {
+        let AccountRows {
+            account,
+            spotify_auth,
+            github_auth,
+            sessions,
+            notification_settings,
+            backup_runs,
+            webhooks,
+            webhook_deliveries,
+            api_tokens,
+        } = rows;
+
+        Self {
+            exported_at: OffsetDateTime::now_utc(),
+            account: AccountExport {
+                id: account.id.to_string(),
+                created_at: account.created_at,
+                deletion_scheduled_at: account.deletion_scheduled_at,
+                feed_enabled: account.feed_token.is_some(),
+                webhook_secret_set: account.webhook_secret.is_some(),
+            },
+            spotify: spotify_auth.map(|spotify| SpotifyExport {
+                user_id: spotify.user_id,
+                created_at: spotify.created_at,
+                access_token_expires_at: spotify.expires_at,
+            }),
+            github: github_auth.map(|github| GithubExport {
+                user_id: github.user_id,
+                created_at: github.created_at,
+                installation_id: github.installation_id,
+                repository_id: github.repository_id,
+                access_token_expires_at: github.expires_at,
+                refresh_token_expires_at: github.refresh_token_expires_at,
+            }),
+            sessions: sessions
+                .into_iter()
+                .map(|session| SessionExport {
+                    current: session.id == current_session.into_uuid(),
+                    created_at: session.created_at,
+                    last_seen: session.last_seen,
+                    user_agent: session.user_agent.clone(),
+                    ip_address: session.ip_address.clone(),
+                    handle: UserSessionId::from_model(session).public_handle(),
+                })
+                .collect(),
+            notification_settings: notification_settings.map(|settings| {
+                NotificationSettingsExport {
+                    email: settings.email,
+                    backup_failures: settings.backup_failures,
+                    authorization_revoked: settings.authorization_revoked,
+                    weekly_summary: settings.weekly_summary,
+                    unavailable_tracks: settings.unavailable_tracks,
+                    last_weekly_summary: settings.last_weekly_summary,
+                    created_at: settings.created_at,
+                }
+            }),
+            backup_runs: backup_runs
+                .into_iter()
+                .map(|run| BackupRunExport {
+                    id: run.id.to_string(),
+                    started_at: run.started_at,
+                    finished_at: run.finished_at,
+                    status: run.status,
+                    commit: run.commit,
+                    summary: run.summary,
+                    diff: run.diff,
+                    error: run.error,
+                })
+                .collect(),
+            webhooks: webhooks
+                .into_iter()
+                .map(|webhook| WebhookExport {
+                    id: webhook.id.to_string(),
+                    url: webhook.url,
+                    created_at: webhook.created_at,
+                })
+                .collect(),
+            webhook_deliveries: webhook_deliveries
+                .into_iter()
+                .map(|delivery| WebhookDeliveryExport {
+                    id: delivery.id.to_string(),
+                    webhook: delivery.webhook.to_string(),
+                    backup_run: delivery.backup_run.to_string(),
+                    attempt: delivery.attempt,
+                    attempted_at: delivery.attempted_at,
+                    status_code: delivery.status_code,
+                    error: delivery.error,
+                })
+                .collect(),
+            api_tokens: api_tokens
+                .into_iter()
+                .map(|token| ApiTokenExport {
+                    id: token.id.to_string(),
+                    name: token.name,
+                    scopes: token.scopes,
+                    created_at: token.created_at,
+                    last_used_at: token.last_used_at,
+                })
+                .collect(),
+            latest_snapshot: None,
+        }
+    }
+}
+
+/// The library as of the most recent committed backup, an unreachable
+/// repository should not stop the rest of the export
+async fn latest_snapshot(
+    user: &User,
+    database: &Database,
+) -> Result<Option<SnapshotExport>, InternalServerError> {
+    let Some(installation) = user.account.backup_installation() else {
+        return Ok(None);
+    };
+
+    let Some(commit) = database
+        .list_changed_backup_runs(user.account.id, 1)
+        .await?
+        .into_iter()
+        .find_map(|run| match run.outcome {
+            BackupOutcome::Committed { commit, .. } => Some(commit),
+            _ => None,
+        })
+    else {
+        return Ok(None);
+    };
+
+    let repository = BackupRepository::for_installation(installation).await?;
+    let snapshot = repository.snapshot_at(&commit).await?;
+
+    Ok(Some(SnapshotExport { commit, snapshot }))
+}
+
+pub async fn export(
+    State(database): State<Database>,
+    user: User,
+) -> Result<Response, InternalServerError> {
+    let rows = database
+        .account_rows(user.account.id)
+        .await?
+        .ok_or_else(|| internal_server_error!("account of the current user should exist"))?;
+
+    let mut export = DataExport::from_rows(rows, UserSessionId::from_model(user.session.clone()));
+
+    export.latest_snapshot = match latest_snapshot(&user, &database).await {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            warn!(?error, "exporting without the latest snapshot");
+
+            None
+        }
+    };
+
+    let body = serde_json::to_vec_pretty(&export).map_err(InternalServerError::from_error)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"spotify-backup-export.json\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
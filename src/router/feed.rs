@@ -0,0 +1,40 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+
+use crate::{database::Database, pages::InternalServerError};
+
+use super::{
+    authentication::User,
+    csrf::{self, CsrfForm},
+};
+
+pub async fn rotate_token(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    database.rotate_feed_token(user.account.id).await?;
+
+    Ok(Redirect::to("/account").into_response())
+}
+
+pub async fn disable(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    database.disable_feed(user.account.id).await?;
+
+    Ok(Redirect::to("/account").into_response())
+}
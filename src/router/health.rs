@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use futures::Future;
+use serde::Serialize;
+
+use crate::{backup, database::Database, pages};
+
+/// Checks taking longer than this are considered failed, so the health check
+/// answers before the platform gives up on it
+const CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Component {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Component {
+    fn ok() -> Self {
+        Self {
+            status: Status::Ok,
+            message: None,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            status: Status::Error,
+            message: Some(message.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    status: Status,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    status: Status,
+    database: Component,
+    migrations: Component,
+    backup_worker: Component,
+}
+
+/// The server is up and handling requests
+pub async fn live() -> Json<Liveness> {
+    Json(Liveness { status: Status::Ok })
+}
+
+/// The server and everything it depends on is able to serve users
+///
+/// The details of failures are only logged, the response is public. A
+/// stopped or stuck backup worker is reported without failing the check
+pub async fn ready(State(database): State<Database>) -> (StatusCode, Json<Readiness>) {
+    let (database_check, migrations_check) = tokio::join!(
+        check("database", database.ping(), |()| Component::ok()),
+        check(
+            "migrations",
+            database.pending_migrations(),
+            |pending| match pending {
+                0 => Component::ok(),
+                pending => Component::error(format!("{pending} pending migrations")),
+            }
+        ),
+    );
+
+    let backup_worker = match backup::worker_status() {
+        backup::WorkerStatus::Running => Component::ok(),
+        backup::WorkerStatus::Stuck { last_tick } => {
+            Component::error(format!("stuck since {}", pages::format_date(last_tick)))
+        }
+        backup::WorkerStatus::Stopped => Component::error("stopped"),
+    };
+
+    // The backup worker is reported, but does not take the server out of
+    // rotation, the web server works fine without it and would never be
+    // restarted
+    let status = if [&database_check, &migrations_check]
+        .iter()
+        .all(|component| component.status == Status::Ok)
+    {
+        Status::Ok
+    } else {
+        Status::Error
+    };
+
+    (
+        match status {
+            Status::Ok => StatusCode::OK,
+            Status::Error => StatusCode::SERVICE_UNAVAILABLE,
+        },
+        Json(Readiness {
+            status,
+            database: database_check,
+            migrations: migrations_check,
+            backup_worker,
+        }),
+    )
+}
+
+async fn check<T, E: std::fmt::Display>(
+    name: &'static str,
+    check: impl Future<Output = Result<T, E>>,
+    component: impl FnOnce(T) -> Component,
+) -> Component {
+    match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(value)) => component(value),
+        Ok(Err(error)) => {
+            tracing::warn!(check = name, %error, "health check failed");
+
+            Component::error("unavailable")
+        }
+        Err(_) => {
+            tracing::warn!(check = name, "health check timed out");
+
+            Component::error("timed out")
+        }
+    }
+}
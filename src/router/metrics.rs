@@ -0,0 +1,46 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prometheus::TEXT_FORMAT;
+use secrecy::ExposeSecret;
+
+use crate::{
+    api_tokens::hash_token,
+    database::Database,
+    environment::HTTP_ENVIRONMENT,
+    metrics,
+    pages::{self, InternalServerError},
+};
+
+use super::api::ApiError;
+
+/// Prometheus metrics, for scrapers that know the `METRICS_TOKEN`
+pub async fn metrics(
+    State(database): State<Database>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let Some(metrics_token) = &HTTP_ENVIRONMENT.metrics_token else {
+        return Ok(pages::not_found("/metrics"));
+    };
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "missing bearer token"))?;
+
+    // Compare the hashes so the comparison does not leak the token
+    if hash_token(token.trim()) != hash_token(metrics_token.expose_secret()) {
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid token"));
+    }
+
+    let (connections, idle_connections) = database.pool_connections();
+    metrics::DATABASE_CONNECTIONS.set(connections.into());
+    metrics::DATABASE_IDLE_CONNECTIONS.set(idle_connections as i64);
+
+    let body = metrics::render().map_err(InternalServerError::from_error)?;
+
+    Ok(([(header::CONTENT_TYPE, TEXT_FORMAT)], body).into_response())
+}
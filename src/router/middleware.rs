@@ -1,4 +1,7 @@
 pub mod catch_panic;
+pub mod error_format;
+pub mod metrics;
 pub mod redirect;
+pub mod request_id;
 pub mod server_information;
 pub mod trace;
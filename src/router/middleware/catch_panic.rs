@@ -14,12 +14,16 @@ use axum::{
     middleware::{FromFnLayer, Next},
 };
 use futures::{Future, FutureExt};
+use tower_http::request_id::RequestId;
 use tracing_error::SpanTrace;
 
+use crate::pages;
+
 #[derive(Debug)]
 pub struct CaughtPanic {
     payload: Box<dyn Any + Send + 'static>,
     pub info: PanicInfo,
+    request_id: Option<String>,
 }
 
 impl CaughtPanic {
@@ -48,6 +52,10 @@ impl CaughtPanic {
     pub fn span_trace(&self) -> &SpanTrace {
         &self.info.span_trace
     }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
 }
 
 #[derive(Debug)]
@@ -114,13 +122,19 @@ pub fn catch_panic_layer<T>(
             let backtrace = Backtrace::force_capture();
             let span_trace = SpanTrace::capture();
 
+            // The previous hook includes the sentry panic handler, which
+            // reports the panic along with the scope
+            let span_trace_context = pages::span_trace_context(&span_trace);
+            sentry::with_scope(
+                |scope| scope.set_context("span trace", span_trace_context),
+                || previous_hook(info),
+            );
+
             *last_panic.lock().expect("mutex should not be poisoned") = Some(PanicInfo {
                 location: info.location().map(Location::from),
                 backtrace,
                 span_trace,
             });
-
-            previous_hook(info)
         }
     }));
 
@@ -152,6 +166,12 @@ fn catch_panic(
     req: Request<Body>,
     next: Next<Body>,
 ) -> Pin<Box<dyn Future<Output = Response<BoxBody>> + Send + 'static>> {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(String::from);
+
     Box::pin(async move {
         // Catch panic before return of future
         let panic_payload = match std::panic::catch_unwind(AssertUnwindSafe(move || next.run(req)))
@@ -171,6 +191,7 @@ fn catch_panic(
         handler(CaughtPanic {
             payload: panic_payload,
             info: panic_info,
+            request_id,
         })
     })
 }
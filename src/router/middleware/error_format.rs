@@ -0,0 +1,94 @@
+use axum::{
+    body::{Body, BoxBody},
+    http::{header, Request},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use tower_http::request_id::RequestId;
+use utoipa::ToSchema;
+
+use crate::pages::ErrorDetails;
+
+/// The json body of an error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Machine readable kind of the error, e.g. `not_found`
+    pub code: &'static str,
+    /// Human readable description of the error
+    pub message: String,
+    /// Identifier of the request, to be included in bug reports
+    pub request_id: Option<String>,
+}
+
+/// Replace the html body of error responses with an [`ErrorBody`] for clients
+/// that prefer json and for everything under `/api`
+pub async fn error_format(req: Request<Body>, next: Next<Body>) -> Response<BoxBody> {
+    let wants_json = req.uri().path().starts_with("/api/")
+        || req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(prefers_json);
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(String::from);
+
+    let response = next.run(req).await;
+
+    if !wants_json {
+        return response;
+    }
+
+    let Some(details) = response.extensions().get::<ErrorDetails>().cloned() else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    let (json_parts, body) = Json(ErrorBody {
+        code: details.code,
+        message: details.message,
+        request_id,
+    })
+    .into_response()
+    .into_parts();
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.extend(json_parts.headers);
+
+    Response::from_parts(parts, body)
+}
+
+/// Whether an `Accept` header ranks `application/json` above `text/html`
+fn prefers_json(accept: &str) -> bool {
+    let quality = |wanted: &str| {
+        let (wanted_type, _) = wanted.split_once('/').unwrap_or((wanted, ""));
+
+        accept
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                let media = params.next()?;
+                let q = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+
+                let specificity = match media.split_once('/')? {
+                    _ if media.eq_ignore_ascii_case(wanted) => 2,
+                    (ty, "*") if ty.eq_ignore_ascii_case(wanted_type) => 1,
+                    ("*", "*") => 0,
+                    _ => return None,
+                };
+
+                Some((specificity, q))
+            })
+            // The most specific range decides the quality
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map_or(0.0, |(_, q)| q)
+    };
+
+    let json = quality("application/json");
+    json > 0.0 && json > quality("text/html")
+}
@@ -0,0 +1,49 @@
+use axum::{
+    body::{Body, BoxBody},
+    extract::MatchedPath,
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+
+use crate::metrics;
+
+/// Record the request in the http metrics, labeled with the route pattern
+/// instead of the path to keep the number of series bounded
+pub async fn track_requests(req: Request<Body>, next: Next<Body>) -> Response<BoxBody> {
+    let method = req.method().clone();
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let route = response
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+    let status = response.status();
+    let labels = [method.as_str(), route, status.as_str()];
+
+    metrics::HTTP_REQUESTS.with_label_values(&labels).inc();
+    metrics::HTTP_REQUEST_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// The matched route is only known inside of the router, pass it out on the
+/// response for [`track_requests`]
+pub async fn expose_matched_path(
+    matched_path: Option<MatchedPath>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response<BoxBody> {
+    let mut response = next.run(req).await;
+
+    if let Some(matched_path) = matched_path {
+        response.extensions_mut().insert(matched_path);
+    }
+
+    response
+}
@@ -0,0 +1,33 @@
+use axum::{
+    body::{Body, BoxBody},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use tower_http::request_id::RequestId;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The `x-request-id` of the request currently being handled, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Make the request id available to [`current_request_id`] and tag the sentry
+/// events of the request with it
+pub async fn scope_request_id(req: Request<Body>, next: Next<Body>) -> Response<BoxBody> {
+    let Some(request_id) = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(String::from)
+    else {
+        return next.run(req).await;
+    };
+
+    sentry::configure_scope(|scope| scope.set_tag("request_id", &request_id));
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
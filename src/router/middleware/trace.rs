@@ -1,16 +1,31 @@
+use std::borrow::Cow;
+
 use axum::http::Request;
 use tower_http::{request_id::RequestId, trace::MakeSpan};
 use tracing::{debug_span, Span};
 
+const FEED_PATH: &str = "/feed/";
+
+/// Replace the token of feed urls, it is the only credential needed to read
+/// a feed so it must not end up in logs or error reports. Works on paths and
+/// anything that contains one, like sentry transaction names.
+pub fn redact_path(path: &str) -> Cow<'_, str> {
+    match path.find(FEED_PATH) {
+        Some(start) => Cow::Owned(format!("{}{FEED_PATH}:token", &path[..start])),
+        None => Cow::Borrowed(path),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SpanMaker;
 
 impl<B> MakeSpan<B> for SpanMaker {
     fn make_span(&mut self, request: &Request<B>) -> Span {
         let method = request.method();
-        let uri = request.uri();
+        let uri = request.uri().to_string();
+        let uri = redact_path(&uri);
         let version = request.version();
-        let id = request
+        let request_id = request
             .extensions()
             .get::<RequestId>()
             .expect("request should contain request ID")
@@ -18,6 +33,6 @@ impl<B> MakeSpan<B> for SpanMaker {
             .to_str()
             .expect("request id should be valid utf-8");
 
-        debug_span!(target: "spotify_backup", "request", %method, %uri, ?version, %id)
+        debug_span!(target: "spotify_backup", "request", %method, %uri, ?version, %request_id)
     }
 }
@@ -0,0 +1,77 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    notifications::{self, Mailer, NotificationSettings},
+    pages::{ClientError, InternalServerError},
+};
+
+use super::{authentication::User, csrf};
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationSettingsForm {
+    csrf: String,
+    email: String,
+    #[serde(default)]
+    backup_failures: bool,
+    #[serde(default)]
+    authorization_revoked: bool,
+    #[serde(default)]
+    weekly_summary: bool,
+    #[serde(default)]
+    unavailable_tracks: bool,
+}
+
+pub async fn update_settings(
+    State(database): State<Database>,
+    State(mailer): State<Mailer>,
+    user: User,
+    Form(form): Form<NotificationSettingsForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    let email = form.email.trim();
+
+    if !email.is_empty() && !mailer.is_enabled() {
+        return Ok(ClientError::new(String::from(
+            "email notifications are turned off on this server",
+        ))
+        .into_response());
+    }
+
+    if email.is_empty() {
+        database
+            .delete_notification_settings(user.account.id)
+            .await?;
+
+        return Ok(Redirect::to("/account").into_response());
+    }
+
+    if email.parse::<lettre::Address>().is_err() {
+        return Ok(
+            ClientError::new(format!("{email:?} is not a valid email address")).into_response(),
+        );
+    }
+
+    let mut settings = match database.get_notification_settings(user.account.id).await? {
+        Some(settings) => settings,
+        None => NotificationSettings::new(user.account.id, email.to_string()),
+    };
+
+    settings.change_email(email.to_string());
+    settings.backup_failures = form.backup_failures;
+    settings.authorization_revoked = form.authorization_revoked;
+    settings.weekly_summary = form.weekly_summary;
+    settings.unavailable_tracks = form.unavailable_tracks;
+
+    notifications::save_settings(&database, &mailer, settings).await?;
+
+    Ok(Redirect::to("/account").into_response())
+}
@@ -1,21 +1,70 @@
-use std::{convert::Infallible, str::FromStr};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
 
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::StatusCode,
-    response::{IntoResponse, IntoResponseParts},
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, StatusCode},
+    response::{IntoResponse, IntoResponseParts, Redirect},
     RequestPartsExt,
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar, Expiration, SameSite};
+use entity::user_session;
 use sea_orm::prelude::Uuid;
 use time::OffsetDateTime;
 use tracing::debug;
 
-use crate::database::id::UserSessionId;
+use crate::{
+    database::{id::UserSessionId, Database},
+    environment::HTTP_ENVIRONMENT,
+};
 
 const SESSION_COOKIE: &str = "spotify-backup-session";
 
+/// Sessions that have not been used for this long are logged out
+pub const IDLE_TIMEOUT: time::Duration = time::Duration::days(14);
+
+/// Sessions are logged out this long after logging in, even if still in use
+pub const ABSOLUTE_TIMEOUT: time::Duration = time::Duration::days(90);
+
+/// `last_seen` is only written when it is older than this, so that not every
+/// request has to write to the database
+pub const LAST_SEEN_INTERVAL: time::Duration = time::Duration::minutes(5);
+
+/// How often expired sessions are deleted from the database
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Whether the session has timed out, either from being idle or from being
+/// too old
+pub fn is_expired(session: &user_session::Model, now: OffsetDateTime) -> bool {
+    now - session.last_seen > IDLE_TIMEOUT || now - session.created_at > ABSOLUTE_TIMEOUT
+}
+
+/// Expired sessions are already rejected when they are used, this removes
+/// the ones that are never used again
+#[tracing::instrument(skip_all)]
+pub async fn prune_expired_sessions(database: Database) -> Infallible {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let now = OffsetDateTime::now_utc();
+
+        // The error has already been reported, pruning is retried next time
+        if let Ok(pruned) = database
+            .prune_user_sessions(now - IDLE_TIMEOUT, now - ABSOLUTE_TIMEOUT)
+            .await
+        {
+            debug!(pruned, "pruned expired sessions");
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct UserSession {
     pub id: UserSessionId,
@@ -33,6 +82,8 @@ impl UserSession {
 pub enum UserSessionRejection {
     NoSessionCookie,
     BadSessionCookie(<Uuid as FromStr>::Err),
+    /// The session timed out and has been logged out
+    Expired,
 }
 
 impl IntoResponse for UserSessionRejection {
@@ -52,6 +103,9 @@ impl IntoResponse for UserSessionRejection {
                 )
                     .into_response()
             }
+            UserSessionRejection::Expired => {
+                (UserSession::remove(), Redirect::to("/")).into_response()
+            }
         }
     }
 }
@@ -84,6 +138,76 @@ impl<S> FromRequestParts<S> for UserSession {
     }
 }
 
+/// The device a session is logged in from, shown so users can recognize
+/// their sessions
+#[derive(Debug, Clone, Default)]
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    /// Only the network part of the address, the exact address is not needed
+    /// to recognize a session
+    pub ip_address: Option<String>,
+}
+
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Only keep the /24 of IPv4 and the /48 of IPv6 addresses
+fn approximate_ip(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(ip) => {
+            let [a, b, c, ..] = ip.segments();
+
+            format!("{a:x}:{b:x}:{c:x}::/48")
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionClient {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+
+        let user_agent = header(USER_AGENT.as_str())
+            .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        // Set by the fly.io proxy, clients connecting directly could set them
+        // to anything, so they are only used behind a trusted proxy
+        let forwarded_ip = HTTP_ENVIRONMENT
+            .trust_proxy
+            .then(|| {
+                header("fly-client-ip").or_else(|| {
+                    header("x-forwarded-for").and_then(|forwarded| forwarded.split(',').next())
+                })
+            })
+            .flatten()
+            .and_then(|ip| ip.trim().parse().ok());
+        let connected_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        let ip_address = forwarded_ip.or(connected_ip).map(approximate_ip);
+
+        Ok(SessionClient {
+            user_agent,
+            ip_address,
+        })
+    }
+}
+
 impl IntoResponseParts for UserSession {
     type Error = Infallible;
 
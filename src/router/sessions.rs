@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+
+use crate::{
+    database::{id::UserSessionId, Database},
+    pages::{ClientError, InternalServerError},
+};
+
+use super::{
+    authentication::User,
+    csrf::{self, CsrfForm},
+};
+
+pub async fn logout(
+    State(database): State<Database>,
+    user: User,
+    Path(handle): Path<String>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    if !database
+        .revoke_user_session(user.account.id, &handle)
+        .await?
+    {
+        return Ok(ClientError::new(String::from(
+            "that session does not exist, it may have already been logged out",
+        ))
+        .into_response());
+    }
+
+    Ok(Redirect::to("/account/sessions").into_response())
+}
+
+pub async fn logout_others(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    let current = UserSessionId::from_model(user.session.clone());
+
+    database
+        .revoke_other_user_sessions(user.account.id, current)
+        .await?;
+
+    Ok(Redirect::to("/account/sessions").into_response())
+}
@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use sea_orm::prelude::Uuid;
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::{
+    database::{id::WebhookId, Database},
+    pages::{ClientError, InternalServerError},
+    webhooks::{validate_url, Webhook, MAX_WEBHOOKS},
+};
+
+use super::{
+    authentication::User,
+    csrf::{self, CsrfForm},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookForm {
+    csrf: String,
+    url: String,
+}
+
+pub async fn create(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<WebhookForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    let url = match validate_url(form.url.trim()) {
+        Ok(url) => url,
+        Err(reason) => {
+            return Ok(ClientError::new(format!(
+                "{:?} can not be used as a webhook: {reason}",
+                form.url
+            ))
+            .into_response())
+        }
+    };
+
+    if database.list_webhooks(user.account.id).await?.len() >= MAX_WEBHOOKS {
+        return Ok(
+            ClientError::new(format!("you can register at most {MAX_WEBHOOKS} webhooks"))
+                .into_response(),
+        );
+    }
+
+    database
+        .create_webhook(Webhook {
+            id: WebhookId::generate(),
+            account: user.account.id,
+            url,
+            created_at: OffsetDateTime::now_utc(),
+        })
+        .await?;
+
+    Ok(Redirect::to("/account/webhooks").into_response())
+}
+
+pub async fn delete(
+    State(database): State<Database>,
+    user: User,
+    Path(webhook): Path<Uuid>,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    if !database
+        .delete_webhook(user.account.id, WebhookId::from_raw(webhook))
+        .await?
+    {
+        return Ok(ClientError::new(String::from("that webhook does not exist")).into_response());
+    }
+
+    Ok(Redirect::to("/account/webhooks").into_response())
+}
+
+pub async fn rotate_secret(
+    State(database): State<Database>,
+    user: User,
+    Form(form): Form<CsrfForm>,
+) -> Result<Response, InternalServerError> {
+    if let Err(mismatch) = csrf::verify(&user.session, &form.csrf) {
+        return Ok(mismatch.into_response());
+    }
+
+    database.rotate_webhook_secret(user.account.id).await?;
+
+    Ok(Redirect::to("/account/webhooks").into_response())
+}